@@ -1,7 +1,7 @@
 use crate::VertexFlag::*;
 use image::ImageFormat;
-use nalgebra::{point, vector, Point3};
-use sdl2::mouse::MouseButton;
+use nalgebra::{point, vector};
+use simg::camera::ArcballCamera;
 use simg::color::*;
 use simg::common::*;
 use simg::input::Input;
@@ -20,64 +20,6 @@ const OBJ: &[u8] = include_bytes!("./assets/basic_3d/dog/dog.obj");
 // const OBJ: &[u8] = include_bytes!("./assets/basic_3d/house/house.obj");
 const TEX: &[u8] = include_bytes!("./assets/basic_3d/dog/color.png");
 
-struct ArcballCamera {
-    pub target: Point3<f32>,
-    pub pitch: f32,
-    pub yaw: f32,
-    pub distance: f32,
-    pub fovy: f32,
-}
-
-impl ArcballCamera {
-    pub fn new(
-        target: Point3<f32>,
-        pitch: f32,
-        yaw: f32,
-        distance: f32,
-        fovy: f32,
-    ) -> Self {
-        Self { target, pitch, yaw, distance, fovy }
-    }
-
-    pub fn update(&mut self, input: &Input) {
-        if input.mouse_buttons.is_pressed(MouseButton::Left) {
-            let dx = input.mouse_xrel as f32;
-            let dy = input.mouse_yrel as f32;
-            self.pitch += dy;
-            self.yaw += dx;
-
-            self.pitch = self.pitch.signum() * self.pitch.abs().min(85.0);
-        }
-
-        self.distance -= input.mouse_wheel as f32;
-    }
-
-    pub fn get_camera(&self) -> Camera {
-        let q = nalgebra::UnitQuaternion::from_euler_angles(
-            -self.pitch.to_radians(),
-            self.yaw.to_radians(),
-            0.0,
-        );
-        let eye = point![0.0, 0.0, self.distance];
-        let eye = (q * (eye - self.target)) + self.target.coords;
-
-        Camera::new_3d(
-            Point3::from(eye),
-            self.target,
-            vector![0.0, 1.0, 0.0],
-        )
-    }
-
-    pub fn get_proj(&self, aspect: f32) -> Projection {
-        Projection::new_perspective(
-            aspect,
-            self.fovy.to_radians(),
-            0.1,
-            1000.0,
-        )
-    }
-}
-
 struct Game {
     dt: f32,
     time: f32,
@@ -92,7 +34,7 @@ struct Game {
 
     camera: ArcballCamera,
 
-    vb_gpu: usize,
+    vb_gpu: VertexBufferIdx,
     tex: Texture,
 }
 
@@ -121,8 +63,11 @@ impl Game {
         // vb_cpu.set_colors(Color::gray(0.3, 1.0));
         // vb_cpu.unset_flags(HasTexture as u8);
         let vb_gpu = renderer.load_vertex_buffer_from_cpu(&vb_cpu);
-        let tex =
-            renderer.load_texture_from_image_bytes(TEX, ImageFormat::Png);
+        let tex = renderer.load_texture_from_image_bytes(
+            TEX,
+            ImageFormat::Png,
+            TextureOptions::new_mipmapped(8.0),
+        );
 
         Self {
             dt: 0.0,
@@ -3,6 +3,7 @@ use nalgebra::{point, Point2, Vector2};
 use rand::Rng;
 use sdl2::keyboard::Keycode;
 use simg::audio_player::AudioPlayer;
+use simg::camera::CameraEffects;
 use simg::color::*;
 use simg::common::*;
 use simg::geometry::*;
@@ -181,6 +182,7 @@ struct Game {
     glyph_tex_small: Texture,
 
     postfx: Program,
+    camera_effects: CameraEffects,
     frame: Rectangle,
     field: Rectangle,
 
@@ -207,11 +209,15 @@ impl Game {
         );
 
         let glyph_atlas_large = GlyphAtlas::new(FONT, 48);
-        let glyph_tex_large =
-            renderer.load_texture_from_glyph_atlas(&glyph_atlas_large);
+        let glyph_tex_large = renderer.load_texture_from_glyph_atlas(
+            &glyph_atlas_large,
+            TextureOptions::default(),
+        );
         let glyph_atlas_small = GlyphAtlas::new(FONT, 24);
-        let glyph_tex_small =
-            renderer.load_texture_from_glyph_atlas(&glyph_atlas_small);
+        let glyph_tex_small = renderer.load_texture_from_glyph_atlas(
+            &glyph_atlas_small,
+            TextureOptions::default(),
+        );
 
         let postfx = renderer.load_screen_rect_program(POSTFX_FRAG_SRC);
 
@@ -234,6 +240,7 @@ impl Game {
             glyph_atlas_small,
             glyph_tex_small,
             postfx,
+            camera_effects: CameraEffects::new(12.0, 0.05),
             frame: Rectangle::zeros(),
             field: Rectangle::zeros(),
             blocks: vec![],
@@ -301,6 +308,7 @@ impl Game {
         use State::*;
 
         self.time += dt;
+        self.camera_effects.update(dt);
 
         match self.state {
             NotStarted => {
@@ -433,6 +441,8 @@ impl Game {
             self.state = State::NotStarted;
             self.ball.is_dead = true;
             self.game_over_time = Some(self.time);
+            self.camera_effects.shake.add_trauma(1.0);
+            self.camera_effects.flash.flash(RED.with_alpha(0.4));
 
             self.audio_player.play_chunk(self.ball_death_sound);
             self.audio_player.fade_out_music(800);
@@ -470,6 +480,8 @@ impl Game {
                 self.ball.velocity = reflect(&self.ball.velocity, &mtv);
                 self.scores += block.score;
                 block.death_time = Some(self.time);
+                self.camera_effects.shake.add_trauma(0.35);
+                self.camera_effects.zoom_punch.punch(0.02);
                 self.audio_player.play_chunk(self.block_death_sound);
                 break;
             }
@@ -488,8 +500,17 @@ impl Game {
     }
 
     fn update_renderer(&mut self) {
-        self.renderer.set_screen_proj();
-        self.renderer.set_screen_camera();
+        let window_size = self.renderer.get_window_size();
+        let proj = Projection::new_screen(window_size);
+        let camera = Camera::new_screen(window_size);
+        self.renderer.set_render_proj(
+            proj,
+            self.camera_effects.apply_to_proj(proj),
+        );
+        self.renderer.set_render_camera(
+            camera,
+            self.camera_effects.apply_to_camera(camera),
+        );
         self.renderer.set_depth_test(false);
 
         // Draw objects and playing filed (w/o texture)
@@ -512,6 +533,20 @@ impl Game {
             self.draw_press_space();
         }
 
+        // Draw screen flash without the camera effects
+        if let Some(color) = self.camera_effects.get_flash_color() {
+            self.renderer.set_proj(proj);
+            self.renderer.set_camera(camera);
+            self.renderer.draw_rect(
+                Rectangle::from_center(
+                    WINDOW_CENTER,
+                    Vector2::new(WINDOW_WIDTH, WINDOW_HEIGHT),
+                ),
+                None,
+                Some(color),
+            );
+        }
+
         // Draw postfx and end drawing
         self.renderer.end_drawing(BLACK, Some(&self.postfx));
 
@@ -0,0 +1,67 @@
+use simg::texture_atlas::*;
+use std::path::Path;
+
+const DEFAULT_MAX_SIZE: u32 = 4096;
+const DEFAULT_PADDING: u32 = 1;
+
+fn print_usage() {
+    println!(
+        "Usage: pack_atlas [--max-size N] [--padding N] \
+         <out.png> <out.json> <image>..."
+    );
+}
+
+fn main() {
+    let mut max_size = DEFAULT_MAX_SIZE;
+    let mut padding = DEFAULT_PADDING;
+    let mut paths = Vec::<String>::new();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut parse_value = || {
+            args.next()
+                .and_then(|value| value.parse::<u32>().ok())
+                .unwrap_or_else(|| panic!("{} expects a number", arg))
+        };
+
+        match arg.as_str() {
+            "--max-size" => max_size = parse_value(),
+            "--padding" => padding = parse_value(),
+            "-h" | "--help" => {
+                print_usage();
+                return;
+            }
+            _ => paths.push(arg),
+        }
+    }
+
+    if paths.len() < 3 {
+        print_usage();
+        std::process::exit(1);
+    }
+
+    let mut builder = TextureAtlasBuilder::new(max_size, padding);
+    for path in &paths[2..] {
+        let path = Path::new(path);
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_else(|| panic!("Bad image path: {:?}", path));
+        let image = image::open(path)
+            .unwrap_or_else(|e| panic!("Can't open {:?}: {}", path, e))
+            .into_rgba8();
+
+        builder.add_image(name, image);
+    }
+
+    let atlas = builder.build();
+    atlas.save(&paths[0], &paths[1]).unwrap();
+
+    let (width, height) = atlas.get_size();
+    println!(
+        "Packed {} images into the {}x{} atlas",
+        atlas.get_regions().len(),
+        width,
+        height
+    );
+}
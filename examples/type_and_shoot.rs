@@ -2,6 +2,7 @@ use core::f32::consts::PI;
 use nalgebra::{point, vector, Point2, Vector2};
 use rand::seq::SliceRandom;
 use sdl2::keyboard::Keycode;
+use simg::camera::CameraEffects;
 use simg::color::*;
 use simg::common::*;
 use simg::geometry::*;
@@ -232,6 +233,7 @@ struct Game {
     should_quit: bool,
 
     camera: Camera2D,
+    camera_effects: CameraEffects,
     player: Player,
     bullets: [Bullet; N_BULLETS_MAX],
     enemies: [Enemy; N_ENEMIES_MAX],
@@ -271,8 +273,10 @@ impl Game {
         );
 
         let glyph_atlas_small = GlyphAtlas::new(FONT, FONT_SMALL_SIZE);
-        let glyph_tex_small =
-            renderer.load_texture_from_glyph_atlas(&glyph_atlas_small);
+        let glyph_tex_small = renderer.load_texture_from_glyph_atlas(
+            &glyph_atlas_small,
+            TextureOptions::default(),
+        );
 
         // Generate possible spawn positions
         let angle_step = 2.0 * PI / N_SPAWN_POSITIONS as f32;
@@ -301,6 +305,7 @@ impl Game {
             should_quit: false,
 
             camera: Camera2D::default(),
+            camera_effects: CameraEffects::new(8.0, 0.03),
             player: Player::new(),
             bullets: [Bullet::default(); N_BULLETS_MAX],
             enemies: [(); N_ENEMIES_MAX].map(|_| Enemy::default()),
@@ -337,6 +342,7 @@ impl Game {
         self.prev_ticks = self.timer.ticks();
         self.should_quit = false;
         self.camera = Camera2D::default();
+        self.camera_effects = CameraEffects::new(8.0, 0.03);
         self.player = Player::new();
         self.bullets.iter_mut().for_each(|b| b.is_alive = false);
         self.enemies.iter_mut().for_each(|e| e.is_alive = false);
@@ -367,6 +373,7 @@ impl Game {
             self.dt = dt.min(GAME_DT);
             self.time += self.dt;
             dt -= GAME_DT;
+            self.camera_effects.update(self.dt);
 
             self.input.update();
             self.update_text_input();
@@ -408,6 +415,20 @@ impl Game {
             }
         }
 
+        // ---------------------------------------------------------------
+        // Draw screen flash
+        if let Some(color) = self.camera_effects.get_flash_color() {
+            self.renderer.set_screen_camera();
+            self.renderer.draw_rect(
+                Rectangle::from_bot_left(
+                    Point2::origin(),
+                    vector![WINDOW_WIDTH, WINDOW_HEIGHT],
+                ),
+                None,
+                Some(color),
+            );
+        }
+
         // ---------------------------------------------------------------
         // Finalize drawing
         self.renderer.end_drawing(CLEAR_COLOR, None);
@@ -501,6 +522,7 @@ impl Game {
                         bullet.is_alive = false;
                         enemy.is_alive = false;
                         self.n_enemies_killed += 1;
+                        self.camera_effects.shake.add_trauma(0.4);
                         break;
                     }
                 }
@@ -606,6 +628,8 @@ impl Game {
         // ---------------------------------------------------------------
         // Update game state
         if is_loss {
+            self.camera_effects.shake.add_trauma(1.0);
+            self.camera_effects.flash.flash(RED.with_alpha(0.5));
             self.change_state(GameState::Loss);
         } else if self.pause_command.is_active(self.playing_time) {
             self.change_state(GameState::Pause);
@@ -636,7 +660,11 @@ impl Game {
         // ---------------------------------------------------------------
         // Draw player, bullets, enemies
         self.renderer.set_screen_proj();
-        self.renderer.set_origin_2d_camera();
+        let camera: Camera = self.camera.into();
+        self.renderer.set_render_camera(
+            camera,
+            self.camera_effects.apply_to_camera(camera),
+        );
 
         self.renderer.draw_circle(
             self.player.circle,
@@ -1,6 +1,11 @@
 const uint UTRUE = uint(1);
 const uint UFALSE = uint(0);
 
+const uint FOG_NONE = uint(0);
+const uint FOG_LINEAR = uint(1);
+const uint FOG_EXPONENTIAL = uint(2);
+const uint FOG_HEIGHT = uint(3);
+
 struct Light {
     vec3 position;
     vec3 color;
@@ -12,22 +17,83 @@ in vec4 vs_color;
 in vec3 vs_normal;
 in vec2 vs_texcoord;
 flat in uint vs_flags;
+flat in vec4 vs_sdf_params;
+flat in vec4 vs_sdf_radii;
 
 out vec4 fs_color;
 
 uniform sampler2D u_tex;
+uniform samplerCube u_env_tex;
+uniform float u_reflectivity;
 uniform uint u_is_font;
 uniform uint u_is_blinn_phong;
 uniform float u_shininess;
 uniform vec3 u_camera_pos;
 uniform uint u_n_lights;
 uniform Light[128] u_lights;
+uniform uint u_fog_mode;
+uniform vec3 u_fog_color;
+uniform vec3 u_fog_params;
 
 const vec3 AMBIENT_COLOR = vec3(0.08, 0.06, 0.04);
 
+float get_fog_factor() {
+    vec3 ray = vs_world_pos - u_camera_pos;
+    float dist = length(ray);
+
+    if (u_fog_mode == FOG_LINEAR) {
+        float start = u_fog_params.x;
+        float end = u_fog_params.y;
+        return clamp((dist - start) / max(end - start, 1e-5), 0.0, 1.0);
+    } else if (u_fog_mode == FOG_EXPONENTIAL) {
+        float density = u_fog_params.x;
+        return 1.0 - exp(-density * dist);
+    } else if (u_fog_mode == FOG_HEIGHT) {
+        float density = u_fog_params.x;
+        float base_height = u_fog_params.y;
+        float falloff = max(u_fog_params.z, 1e-5);
+
+        // Fog density integrated along the view ray
+        float cam_density = exp(-falloff * (u_camera_pos.y - base_height));
+        float dy = falloff * ray.y;
+        float k = abs(dy) > 1e-4 ? (1.0 - exp(-dy)) / dy : 1.0;
+        return 1.0 - exp(-density * cam_density * k * dist);
+    }
+
+    return 0.0;
+}
+
+// Signed distance to the rounded box with per-corner radii (bot-left,
+// bot-right, top-right, top-left). Non-zero thickness makes an outline
+float get_sdf_distance(vec2 p) {
+    vec2 half_size = vs_sdf_params.xy;
+    float thickness = vs_sdf_params.z;
+
+    float radius = p.x > 0.0
+        ? (p.y > 0.0 ? vs_sdf_radii.z : vs_sdf_radii.y)
+        : (p.y > 0.0 ? vs_sdf_radii.w : vs_sdf_radii.x);
+    vec2 q = abs(p) - half_size + radius;
+    float dist = min(max(q.x, q.y), 0.0) + length(max(q, 0.0)) - radius;
+
+    if (thickness > 0.0) {
+        dist = abs(dist + 0.5 * thickness) - 0.5 * thickness;
+    }
+
+    return dist;
+}
+
 void main() {
     vec4 color = vs_color;
 
+    if ((vs_flags & HasSdf) != UFALSE) {
+        float dist = get_sdf_distance(vs_texcoord);
+        float aa_width = max(fwidth(dist), 1e-5);
+        color.a *= clamp(0.5 - dist / aa_width, 0.0, 1.0);
+        if (color.a <= 0.0) {
+            discard;
+        }
+    }
+
     if ((vs_flags & HasTexture) != UFALSE) {
         vec2 uv = vs_texcoord;
         vec4 tex_color = texture(u_tex, uv); 
@@ -69,6 +135,17 @@ void main() {
         color = vec4(blinn_phong_color, color.a);
     }
 
+    if ((vs_flags & HasNormal) != UFALSE && u_reflectivity > 0.0) {
+        vec3 view_dir = normalize(vs_world_pos - u_camera_pos);
+        vec3 reflect_dir = reflect(view_dir, normalize(vs_normal));
+        vec3 env_color = texture(u_env_tex, reflect_dir).rgb;
+        color.rgb = mix(color.rgb, env_color, u_reflectivity);
+    }
+
+    if (u_fog_mode != FOG_NONE) {
+        color.rgb = mix(color.rgb, u_fog_color, get_fog_factor());
+    }
+
     fs_color = color;
 }
 
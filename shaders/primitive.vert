@@ -1,32 +1,57 @@
+const int MAX_N_JOINTS = 128;
+
 in vec3 a_position;
 in vec3 a_normal;
 in vec2 a_texcoord;
 in vec4 a_color;
 in uint a_flags;
+in uvec4 a_joints;
+in vec4 a_weights;
+in vec4 a_sdf_params;
+in vec4 a_sdf_radii;
 
 uniform mat4 u_model_mat;
 uniform mat4 u_view_mat;
 uniform mat4 u_proj_mat;
+uniform uint u_is_skinned;
+
+layout(std140) uniform u_joints_block {
+    mat4 u_joint_mats[MAX_N_JOINTS];
+};
 
 out vec3 vs_world_pos;
 out vec4 vs_color;
 out vec3 vs_normal;
 out vec2 vs_texcoord;
 flat out uint vs_flags;
+flat out vec4 vs_sdf_params;
+flat out vec4 vs_sdf_radii;
 
 void main() {
     mat4 mvp_mat = u_proj_mat * u_view_mat * u_model_mat;
     mat3 normal_mat = transpose(inverse(mat3(u_model_mat)));
 
     vec4 position = vec4(a_position, 1.0);
+    vec3 skinned_normal = a_normal;
+    if ((a_flags & HasSkin) != uint(0) && u_is_skinned == uint(1)) {
+        mat4 skin_mat = a_weights.x * u_joint_mats[a_joints.x]
+            + a_weights.y * u_joint_mats[a_joints.y]
+            + a_weights.z * u_joint_mats[a_joints.z]
+            + a_weights.w * u_joint_mats[a_joints.w];
+        position = skin_mat * position;
+        skinned_normal = mat3(skin_mat) * skinned_normal;
+    }
+
     vec3 world_position = (u_model_mat * position).xyz;
     vec4 proj_position = mvp_mat * position;
-    vec3 normal = normal_mat * a_normal;
+    vec3 normal = normal_mat * skinned_normal;
 
     vs_world_pos = world_position;
     vs_color = a_color;
-    vs_normal = a_normal;
+    vs_normal = skinned_normal;
     vs_texcoord = a_texcoord;
     vs_flags = a_flags;
+    vs_sdf_params = a_sdf_params;
+    vs_sdf_radii = a_sdf_radii;
     gl_Position = proj_position;
 }
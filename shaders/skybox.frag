@@ -0,0 +1,13 @@
+in vec2 vs_ndc;
+
+out vec4 fs_color;
+
+uniform samplerCube u_cubemap;
+uniform mat4 u_inv_view_proj_mat;
+
+void main() {
+    vec4 world_pos = u_inv_view_proj_mat * vec4(vs_ndc, 1.0, 1.0);
+    vec3 dir = normalize(world_pos.xyz / world_pos.w);
+
+    fs_color = texture(u_cubemap, dir);
+}
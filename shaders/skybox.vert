@@ -0,0 +1,13 @@
+out vec2 vs_ndc;
+
+const vec2 RECT_IDX_TO_NDC[4] = vec2[4](
+    vec2(-1.0, -1.0),
+    vec2(1.0, -1.0),
+    vec2(-1.0, 1.0),
+    vec2(1.0, 1.0)
+);
+
+void main() {
+    vs_ndc = RECT_IDX_TO_NDC[gl_VertexID];
+    gl_Position = vec4(vs_ndc, 1.0, 1.0);
+}
@@ -0,0 +1,187 @@
+use crate::common::*;
+use nalgebra::{point, Matrix4, Point3, Vector3, Vector4};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl BoundingBox {
+    pub fn new(min: Point3<f32>, max: Point3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    pub fn zeros() -> Self {
+        Self::new(Point3::origin(), Point3::origin())
+    }
+
+    pub fn from_points<'a>(
+        points: impl IntoIterator<Item = &'a Point3<f32>>,
+    ) -> Self {
+        let mut points = points.into_iter();
+        let first = match points.next() {
+            Some(first) => *first,
+            None => return Self::zeros(),
+        };
+
+        points.fold(Self::new(first, first), |bbox, p| {
+            Self::new(bbox.min.inf(p), bbox.max.sup(p))
+        })
+    }
+
+    pub fn from_positions(positions: &[f32]) -> Self {
+        let points: Vec<Point3<f32>> = positions
+            .chunks_exact(3)
+            .map(|p| point![p[0], p[1], p[2]])
+            .collect();
+
+        Self::from_points(points.iter())
+    }
+
+    pub fn get_center(&self) -> Point3<f32> {
+        nalgebra::center(&self.min, &self.max)
+    }
+
+    pub fn get_size(&self) -> Vector3<f32> {
+        self.max - self.min
+    }
+
+    pub fn get_corners(&self) -> [Point3<f32>; 8] {
+        let (min, max) = (self.min, self.max);
+
+        [
+            point![min.x, min.y, min.z],
+            point![max.x, min.y, min.z],
+            point![min.x, max.y, min.z],
+            point![max.x, max.y, min.z],
+            point![min.x, min.y, max.z],
+            point![max.x, min.y, max.z],
+            point![min.x, max.y, max.z],
+            point![max.x, max.y, max.z],
+        ]
+    }
+
+    pub fn get_bounding_sphere(&self) -> BoundingSphere {
+        BoundingSphere::new(
+            self.get_center(),
+            self.get_size().norm() * 0.5,
+        )
+    }
+
+    pub fn contains_point(&self, point: &Point3<f32>) -> bool {
+        (0..3).all(|i| point[i] >= self.min[i] && point[i] <= self.max[i])
+    }
+
+    pub fn transform(&self, mat: &Matrix4<f32>) -> Self {
+        let corners = self.get_corners().map(|p| mat.transform_point(&p));
+
+        Self::from_points(corners.iter())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Point3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    pub fn new(center: Point3<f32>, radius: f32) -> Self {
+        Self { center, radius }
+    }
+
+    pub fn from_positions(positions: &[f32]) -> Self {
+        let center = BoundingBox::from_positions(positions).get_center();
+        let radius = positions
+            .chunks_exact(3)
+            .map(|p| (point![p[0], p[1], p[2]] - center).norm())
+            .fold(0.0, f32::max);
+
+        Self::new(center, radius)
+    }
+
+    pub fn contains_point(&self, point: &Point3<f32>) -> bool {
+        (point - self.center).norm_squared() <= self.radius * self.radius
+    }
+
+    pub fn transform(&self, mat: &Matrix4<f32>) -> Self {
+        let max_scale = (0..3)
+            .map(|i| mat.fixed_view::<3, 1>(0, i).norm())
+            .fold(0.0, f32::max);
+
+        Self::new(
+            mat.transform_point(&self.center),
+            self.radius * max_scale,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    // Left, right, bottom, top, near and far planes. Normals look inside
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    pub fn new(camera: &Camera, proj: &Projection) -> Self {
+        Self::from_mat(&(proj.get_mat() * camera.get_mat()))
+    }
+
+    pub fn from_mat(view_proj_mat: &Matrix4<f32>) -> Self {
+        let m = view_proj_mat;
+        let row = |i: usize| m.row(i).transpose();
+        let mut planes = [
+            row(3) + row(0),
+            row(3) - row(0),
+            row(3) + row(1),
+            row(3) - row(1),
+            row(3) + row(2),
+            row(3) - row(2),
+        ];
+
+        for plane in planes.iter_mut() {
+            let norm = plane.xyz().norm();
+            if norm > f32::EPSILON {
+                *plane /= norm;
+            }
+        }
+
+        Self { planes }
+    }
+
+    pub fn get_planes(&self) -> &[Vector4<f32>; 6] {
+        &self.planes
+    }
+
+    pub fn contains_point(&self, point: &Point3<f32>) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| get_plane_distance(plane, point) >= 0.0)
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes.iter().all(|plane| {
+            get_plane_distance(plane, &sphere.center) >= -sphere.radius
+        })
+    }
+
+    pub fn intersects_box(&self, bbox: &BoundingBox) -> bool {
+        self.planes.iter().all(|plane| {
+            // The box corner which is the farthest along the plane normal
+            let p = Point3::from(Vector3::from_fn(|i, _| {
+                if plane[i] >= 0.0 {
+                    bbox.max[i]
+                } else {
+                    bbox.min[i]
+                }
+            }));
+
+            get_plane_distance(plane, &p) >= 0.0
+        })
+    }
+}
+
+fn get_plane_distance(plane: &Vector4<f32>, point: &Point3<f32>) -> f32 {
+    plane.xyz().dot(&point.coords) + plane.w
+}
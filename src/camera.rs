@@ -0,0 +1,535 @@
+use crate::color::Color;
+use crate::common::*;
+use crate::input::Input;
+use crate::shapes::Rectangle;
+use nalgebra::{
+    point, vector, Point2, Point3, Unit, UnitQuaternion, Vector2, Vector3,
+};
+use sdl2::keyboard::Scancode;
+use sdl2::mouse::MouseButton;
+
+const MAX_PITCH: f32 = 85.0;
+const SHAKE_NOISE_SEEDS: [u32; 4] = [17, 59, 131, 233];
+
+pub struct ArcballCamera {
+    pub target: Point3<f32>,
+    pub pitch: f32,
+    pub yaw: f32,
+    pub distance: f32,
+    pub fovy: f32,
+
+    pub rotation_speed: f32,
+    pub pan_speed: f32,
+    pub zoom_speed: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+}
+
+impl ArcballCamera {
+    pub fn new(
+        target: Point3<f32>,
+        pitch: f32,
+        yaw: f32,
+        distance: f32,
+        fovy: f32,
+    ) -> Self {
+        Self {
+            target,
+            pitch,
+            yaw,
+            distance,
+            fovy,
+            rotation_speed: 1.0,
+            pan_speed: 0.002,
+            zoom_speed: 1.0,
+            min_distance: 0.1,
+            max_distance: 1000.0,
+        }
+    }
+
+    pub fn update(&mut self, input: &Input) {
+        let dx = input.mouse_xrel as f32;
+        let dy = input.mouse_yrel as f32;
+
+        if input.mouse_buttons.is_pressed(MouseButton::Left) {
+            self.pitch += dy * self.rotation_speed;
+            self.yaw += dx * self.rotation_speed;
+            self.pitch = self.pitch.clamp(-MAX_PITCH, MAX_PITCH);
+        }
+
+        if input.mouse_buttons.is_pressed(MouseButton::Right) {
+            let rotation = self.get_rotation();
+            let right = rotation * Vector3::x();
+            let up = rotation * Vector3::y();
+            let speed = self.pan_speed * self.distance;
+            self.target += (-right * dx + up * dy) * speed;
+        }
+
+        self.distance -= input.mouse_wheel as f32 * self.zoom_speed;
+        self.distance =
+            self.distance.clamp(self.min_distance, self.max_distance);
+    }
+
+    pub fn get_position(&self) -> Point3<f32> {
+        let eye = self.get_rotation() * vector![0.0, 0.0, self.distance];
+
+        self.target + eye
+    }
+
+    pub fn get_camera(&self) -> Camera {
+        Camera::new_3d(
+            self.get_position(),
+            self.target,
+            vector![0.0, 1.0, 0.0],
+        )
+    }
+
+    pub fn get_proj(&self, aspect: f32) -> Projection {
+        Projection::new_perspective(
+            aspect,
+            self.fovy.to_radians(),
+            0.1,
+            1000.0,
+        )
+    }
+
+    fn get_rotation(&self) -> UnitQuaternion<f32> {
+        UnitQuaternion::from_euler_angles(
+            -self.pitch.to_radians(),
+            self.yaw.to_radians(),
+            0.0,
+        )
+    }
+}
+
+pub struct FlyCamera {
+    pub position: Point3<f32>,
+    pub pitch: f32,
+    pub yaw: f32,
+    pub fovy: f32,
+
+    pub move_speed: f32,
+    pub boost_factor: f32,
+    pub look_speed: f32,
+}
+
+impl FlyCamera {
+    pub fn new(
+        position: Point3<f32>,
+        pitch: f32,
+        yaw: f32,
+        fovy: f32,
+    ) -> Self {
+        Self {
+            position,
+            pitch,
+            yaw,
+            fovy,
+            move_speed: 5.0,
+            boost_factor: 4.0,
+            look_speed: 0.2,
+        }
+    }
+
+    // Looks around while the right mouse button is held, moves with WASD,
+    // goes up and down with E and Q and moves faster with Shift
+    pub fn update(&mut self, input: &Input, dt: f32) {
+        if input.mouse_buttons.is_pressed(MouseButton::Right) {
+            self.yaw -= input.mouse_xrel as f32 * self.look_speed;
+            self.pitch -= input.mouse_yrel as f32 * self.look_speed;
+            self.pitch = self.pitch.clamp(-MAX_PITCH, MAX_PITCH);
+        }
+
+        let forward = self.get_forward();
+        let right = forward.cross(&Vector3::y()).normalize();
+        let mut direction = Vector3::zeros();
+        let bindings = [
+            (Scancode::W, forward),
+            (Scancode::S, -forward),
+            (Scancode::D, right),
+            (Scancode::A, -right),
+            (Scancode::E, Vector3::y()),
+            (Scancode::Q, -Vector3::y()),
+        ];
+        for (scancode, dir) in bindings {
+            if input.scancodes.is_pressed(scancode) {
+                direction += dir;
+            }
+        }
+
+        if direction.norm_squared() > f32::EPSILON {
+            let mut speed = self.move_speed;
+            if input.scancodes.is_pressed(Scancode::LShift) {
+                speed *= self.boost_factor;
+            }
+            self.position += direction.normalize() * speed * dt;
+        }
+    }
+
+    pub fn get_forward(&self) -> Vector3<f32> {
+        let (pitch, yaw) =
+            (self.pitch.to_radians(), self.yaw.to_radians());
+
+        vector![
+            -yaw.sin() * pitch.cos(),
+            pitch.sin(),
+            -yaw.cos() * pitch.cos()
+        ]
+    }
+
+    pub fn get_camera(&self) -> Camera {
+        Camera::new_3d(
+            self.position,
+            self.position + self.get_forward(),
+            vector![0.0, 1.0, 0.0],
+        )
+    }
+
+    pub fn get_proj(&self, aspect: f32) -> Projection {
+        Projection::new_perspective(
+            aspect,
+            self.fovy.to_radians(),
+            0.1,
+            1000.0,
+        )
+    }
+}
+
+pub struct FollowCamera2D {
+    pub position: Point2<f32>,
+    pub view_size: Vector2<f32>,
+    pub zoom: f32,
+
+    // Fraction of the distance to the target which is covered per second
+    pub smoothing: f32,
+    // Half-size of the area around the camera center where the target
+    // can move without dragging the camera
+    pub dead_zone: Vector2<f32>,
+    pub bounds: Option<Rectangle>,
+
+    pub zoom_speed: f32,
+    pub min_zoom: f32,
+    pub max_zoom: f32,
+}
+
+impl FollowCamera2D {
+    pub fn new(position: Point2<f32>, view_size: Vector2<f32>) -> Self {
+        Self {
+            position,
+            view_size,
+            zoom: 1.0,
+            smoothing: 0.95,
+            dead_zone: Vector2::zeros(),
+            bounds: None,
+            zoom_speed: 0.1,
+            min_zoom: 0.1,
+            max_zoom: 10.0,
+        }
+    }
+
+    pub fn update(&mut self, target: Point2<f32>, input: &Input, dt: f32) {
+        self.zoom *= 1.0 + input.mouse_wheel as f32 * self.zoom_speed;
+        self.zoom = self.zoom.clamp(self.min_zoom, self.max_zoom);
+
+        let diff = target - self.position;
+        let excess = vector![
+            diff.x.signum() * (diff.x.abs() - self.dead_zone.x).max(0.0),
+            diff.y.signum() * (diff.y.abs() - self.dead_zone.y).max(0.0)
+        ];
+        let k = if self.smoothing > 0.0 {
+            1.0 - (1.0 - self.smoothing.min(1.0)).powf(dt)
+        } else {
+            1.0
+        };
+        self.position += excess * k;
+
+        self.clamp_to_bounds();
+    }
+
+    pub fn snap_to(&mut self, target: Point2<f32>) {
+        self.position = target;
+        self.clamp_to_bounds();
+    }
+
+    pub fn get_visible_rect(&self) -> Rectangle {
+        Rectangle::from_center(self.position, self.view_size / self.zoom)
+    }
+
+    pub fn get_camera(&self) -> Camera {
+        Camera::new_2d(self.position, 0.0, self.zoom)
+    }
+
+    pub fn get_proj(&self) -> Projection {
+        Projection::new_2d(self.view_size.x, self.view_size.y)
+    }
+
+    fn clamp_to_bounds(&mut self) {
+        let Some(bounds) = self.bounds else {
+            return;
+        };
+
+        let half_size = self.view_size / self.zoom * 0.5;
+        let clamp_axis = |p: f32, min: f32, max: f32, half: f32| {
+            if max - min < 2.0 * half {
+                (min + max) * 0.5
+            } else {
+                p.clamp(min + half, max - half)
+            }
+        };
+
+        self.position = point![
+            clamp_axis(
+                self.position.x,
+                bounds.get_min_x(),
+                bounds.get_max_x(),
+                half_size.x
+            ),
+            clamp_axis(
+                self.position.y,
+                bounds.get_min_y(),
+                bounds.get_max_y(),
+                half_size.y
+            )
+        ];
+    }
+}
+
+// Trauma based shake: the shake strength is trauma squared, trauma decays
+// linearly over time and offsets are sampled from smooth noise
+pub struct CameraShake {
+    pub trauma: f32,
+    // Trauma lost per second
+    pub decay: f32,
+    pub max_offset: f32,
+    // In radians
+    pub max_rotation: f32,
+    // Noise samples per second
+    pub frequency: f32,
+
+    time: f32,
+}
+
+impl CameraShake {
+    pub fn new(max_offset: f32, max_rotation: f32) -> Self {
+        Self {
+            trauma: 0.0,
+            decay: 1.0,
+            max_offset,
+            max_rotation,
+            frequency: 15.0,
+            time: 0.0,
+        }
+    }
+
+    pub fn add_trauma(&mut self, trauma: f32) {
+        self.trauma = (self.trauma + trauma).clamp(0.0, 1.0);
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.time += dt;
+        self.trauma = (self.trauma - self.decay * dt).max(0.0);
+    }
+
+    pub fn get_offset(&self) -> Vector3<f32> {
+        let shake = self.get_shake();
+        let x = self.time * self.frequency;
+
+        vector![
+            get_noise_1d(x, SHAKE_NOISE_SEEDS[0]),
+            get_noise_1d(x, SHAKE_NOISE_SEEDS[1]),
+            get_noise_1d(x, SHAKE_NOISE_SEEDS[2])
+        ] * self.max_offset
+            * shake
+    }
+
+    pub fn get_rotation(&self) -> f32 {
+        let x = self.time * self.frequency;
+
+        get_noise_1d(x, SHAKE_NOISE_SEEDS[3])
+            * self.max_rotation
+            * self.get_shake()
+    }
+
+    pub fn apply(&self, camera: Camera) -> Camera {
+        if self.trauma <= 0.0 {
+            return camera;
+        }
+
+        let offset = self.get_offset();
+        let angle = self.get_rotation();
+
+        match camera {
+            Camera::Cam2D { position, rotation, zoom } => Camera::Cam2D {
+                position: position + offset.xy(),
+                rotation: rotation + angle,
+                zoom,
+            },
+            Camera::Cam3D { position, target, up } => {
+                let axis = Unit::new_normalize(target - position);
+                let roll = UnitQuaternion::from_axis_angle(&axis, angle);
+
+                Camera::Cam3D {
+                    position: position + offset,
+                    target: target + offset,
+                    up: roll * up,
+                }
+            }
+        }
+    }
+
+    fn get_shake(&self) -> f32 {
+        self.trauma * self.trauma
+    }
+}
+
+// Timed zoom-in which eases out back to the original zoom
+pub struct ZoomPunch {
+    pub strength: f32,
+    pub duration: f32,
+
+    time_left: f32,
+}
+
+impl ZoomPunch {
+    pub fn new(duration: f32) -> Self {
+        Self { strength: 0.0, duration, time_left: 0.0 }
+    }
+
+    pub fn punch(&mut self, strength: f32) {
+        self.strength = strength;
+        self.time_left = self.duration;
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.time_left = (self.time_left - dt).max(0.0);
+    }
+
+    pub fn get_zoom(&self) -> f32 {
+        if self.duration <= 0.0 {
+            return 1.0;
+        }
+
+        let k = self.time_left / self.duration;
+        1.0 + self.strength * k * k
+    }
+
+    pub fn apply(&self, proj: Projection) -> Projection {
+        let zoom = self.get_zoom();
+
+        match proj {
+            Projection::Orthographic {
+                view_width,
+                view_height,
+                znear,
+                zfar,
+            } => Projection::Orthographic {
+                view_width: view_width / zoom,
+                view_height: view_height / zoom,
+                znear,
+                zfar,
+            },
+            Projection::Perspective { aspect, fovy, znear, zfar } => {
+                Projection::Perspective {
+                    aspect,
+                    fovy: fovy / zoom,
+                    znear,
+                    zfar,
+                }
+            }
+        }
+    }
+}
+
+pub struct ScreenFlash {
+    pub color: Color,
+    pub duration: f32,
+
+    time_left: f32,
+}
+
+impl ScreenFlash {
+    pub fn new(duration: f32) -> Self {
+        Self {
+            color: Color::gray(1.0, 1.0),
+            duration,
+            time_left: 0.0,
+        }
+    }
+
+    pub fn flash(&mut self, color: Color) {
+        self.color = color;
+        self.time_left = self.duration;
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.time_left = (self.time_left - dt).max(0.0);
+    }
+
+    pub fn get_color(&self) -> Option<Color> {
+        if self.time_left <= 0.0 || self.duration <= 0.0 {
+            return None;
+        }
+
+        let k = self.time_left / self.duration;
+        Some(self.color.with_alpha(self.color.a * k))
+    }
+}
+
+// Render-only camera effects. Gameplay keeps its own camera and the
+// effects are applied to the copy which is passed to the renderer as
+// the render one (see `Renderer::set_render_camera`)
+pub struct CameraEffects {
+    pub shake: CameraShake,
+    pub zoom_punch: ZoomPunch,
+    pub flash: ScreenFlash,
+}
+
+impl CameraEffects {
+    pub fn new(max_offset: f32, max_rotation: f32) -> Self {
+        Self {
+            shake: CameraShake::new(max_offset, max_rotation),
+            zoom_punch: ZoomPunch::new(0.2),
+            flash: ScreenFlash::new(0.2),
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.shake.update(dt);
+        self.zoom_punch.update(dt);
+        self.flash.update(dt);
+    }
+
+    pub fn apply_to_camera(&self, camera: Camera) -> Camera {
+        self.shake.apply(camera)
+    }
+
+    pub fn apply_to_proj(&self, proj: Projection) -> Projection {
+        self.zoom_punch.apply(proj)
+    }
+
+    pub fn get_flash_color(&self) -> Option<Color> {
+        self.flash.get_color()
+    }
+}
+
+// 1D gradient (Perlin) noise in the [-1, 1] range
+fn get_noise_1d(x: f32, seed: u32) -> f32 {
+    let get_gradient = |i: i32| {
+        let mut h = (i as u32).wrapping_mul(0x27d4eb2d) ^ seed;
+        h ^= h >> 15;
+        h = h.wrapping_mul(0x85ebca6b);
+        h ^= h >> 13;
+
+        (h & 0xffff) as f32 / 32767.5 - 1.0
+    };
+
+    let i = x.floor();
+    let f = x - i;
+    let i = i as i32;
+    let a = get_gradient(i) * f;
+    let b = get_gradient(i + 1) * (f - 1.0);
+    let k = f * f * f * (f * (f * 6.0 - 15.0) + 10.0);
+
+    // Gradient noise in 1D peaks at 0.5, so rescale it to the full range
+    (a + (b - a) * k) * 2.0
+}
@@ -1,8 +1,13 @@
-use nalgebra::{point, vector, Matrix4, Point2, Point3, Vector3};
+use nalgebra::{
+    point, vector, Matrix4, Point2, Point3, Rotation3, UnitQuaternion,
+    Vector3,
+};
 
 use crate::color::Color;
 use std::collections::HashMap;
+use std::ops::Mul;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PivotType {
     BotLeft,
     TopLeft,
@@ -15,6 +20,7 @@ pub enum PivotType {
     RightCenter,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Pivot {
     pub ty: PivotType,
     pub p: Point2<f32>,
@@ -89,27 +95,245 @@ pub struct Texture {
     pub idx: u32,
     pub width: u32,
     pub height: u32,
+    // None for the block-compressed textures, which can't be updated
+    pub format: Option<TextureFormat>,
+    // Mip levels are loaded from the file (KTX2, DDS) and never generated
+    pub has_preloaded_levels: bool,
 }
 
 impl Texture {
-    pub fn new(idx: u32, width: u32, height: u32) -> Self {
-        Self { idx, width, height }
+    pub fn new(
+        idx: u32,
+        width: u32,
+        height: u32,
+        format: Option<TextureFormat>,
+    ) -> Self {
+        Self {
+            idx,
+            width,
+            height,
+            format,
+            has_preloaded_levels: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextureFormat {
+    // Single 8-bit channel which is sampled as alpha (glyph atlases)
+    Alpha8,
+    R8,
+    Rg8,
+    Rgb8,
+    Rgba8,
+    // Color channels are converted from sRGB to linear on sampling
+    Srgb8,
+    Srgb8Alpha8,
+    // Half floats (f16 bytes are expected)
+    R16F,
+    Rgba16F,
+    Rgba32F,
+}
+
+impl TextureFormat {
+    // Single channel is treated as alpha to keep the glyph atlases
+    // working, use `R8` explicitly for the data textures
+    pub fn from_n_components(n_components: usize) -> Self {
+        match n_components {
+            1 => Self::Alpha8,
+            2 => Self::Rg8,
+            3 => Self::Rgb8,
+            4 => Self::Rgba8,
+            _ => panic!(
+                "Can't load texture with {}-components pixel",
+                n_components
+            ),
+        }
+    }
+
+    pub fn get_pixel_size(&self) -> usize {
+        match self {
+            Self::Alpha8 | Self::R8 => 1,
+            Self::Rg8 | Self::R16F => 2,
+            Self::Rgb8 | Self::Srgb8 => 3,
+            Self::Rgba8 | Self::Srgb8Alpha8 => 4,
+            Self::Rgba16F => 8,
+            Self::Rgba32F => 16,
+        }
+    }
+}
+
+// Block-compressed formats of the KTX2 and DDS textures. All of them
+// use 4x4 pixel blocks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompressedTextureFormat {
+    Bc1,
+    Bc1Srgb,
+    Bc2,
+    Bc2Srgb,
+    Bc3,
+    Bc3Srgb,
+    Bc4,
+    Bc5,
+    Bc6H,
+    Bc6HSigned,
+    Bc7,
+    Bc7Srgb,
+    Etc2Rgb8,
+    Etc2Srgb8,
+    Etc2Rgba8,
+    Etc2Srgb8Alpha8,
+    Astc4x4,
+    Astc4x4Srgb,
+}
+
+impl CompressedTextureFormat {
+    pub fn get_block_size(&self) -> usize {
+        match self {
+            Self::Bc1
+            | Self::Bc1Srgb
+            | Self::Bc4
+            | Self::Etc2Rgb8
+            | Self::Etc2Srgb8 => 8,
+            _ => 16,
+        }
+    }
+
+    // Size in bytes of the level with the given pixel size
+    pub fn get_level_size(&self, width: u32, height: u32) -> usize {
+        let n_blocks = width.div_ceil(4) * height.div_ceil(4);
+        n_blocks as usize * self.get_block_size()
+    }
+}
+
+// Pair of textures which are updated in turn, so the upload doesn't wait
+// for the GPU to finish drawing with the texture of the previous frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StreamingTexture {
+    textures: [Texture; 2],
+    front_idx: usize,
+}
+
+impl StreamingTexture {
+    pub fn new(textures: [Texture; 2]) -> Self {
+        Self { textures, front_idx: 0 }
+    }
+
+    // Texture with the latest uploaded data
+    pub fn get_texture(&self) -> Texture {
+        self.textures[self.front_idx]
+    }
+
+    // Texture which receives the next upload
+    pub fn get_back_texture(&self) -> Texture {
+        self.textures[1 - self.front_idx]
     }
+
+    pub fn swap(&mut self) {
+        self.front_idx = 1 - self.front_idx;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextureFilter {
+    Nearest,
+    Linear,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextureWrap {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureOptions {
+    pub min_filter: TextureFilter,
+    pub mag_filter: TextureFilter,
+    pub wrap_s: TextureWrap,
+    pub wrap_t: TextureWrap,
+    // Filter between the mipmap levels, None disables mipmapping.
+    // Mipmaps are generated when the texture gets this option
+    pub mipmap_filter: Option<TextureFilter>,
+    // Max anisotropy, 1.0 disables the anisotropic filtering. Clamped to
+    // the value supported by the hardware
+    pub anisotropy: f32,
+}
+
+impl TextureOptions {
+    pub fn new(filter: TextureFilter, wrap: TextureWrap) -> Self {
+        Self {
+            min_filter: filter,
+            mag_filter: filter,
+            wrap_s: wrap,
+            wrap_t: wrap,
+            mipmap_filter: None,
+            anisotropy: 1.0,
+        }
+    }
+
+    // Sharp pixels for the pixel-art sprites
+    pub fn new_nearest() -> Self {
+        Self::new(TextureFilter::Nearest, TextureWrap::ClampToEdge)
+    }
+
+    // Trilinear mipmapped filtering for the 3D surfaces
+    pub fn new_mipmapped(anisotropy: f32) -> Self {
+        Self {
+            mipmap_filter: Some(TextureFilter::Linear),
+            anisotropy,
+            ..Self::new(TextureFilter::Linear, TextureWrap::Repeat)
+        }
+    }
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self::new(TextureFilter::Linear, TextureWrap::Repeat)
+    }
+}
+
+// Handle of the vertex buffer uploaded to the GPU. Slots of the freed
+// buffers are reused, so the generation tells the stale handle apart
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VertexBufferIdx {
+    pub idx: usize,
+    pub generation: u32,
+}
+
+impl VertexBufferIdx {
+    pub fn new(idx: usize, generation: u32) -> Self {
+        Self { idx, generation }
+    }
+}
+
+// Cube texture with faces in the +X, -X, +Y, -Y, +Z, -Z order
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cubemap {
+    pub idx: u32,
+    pub size: u32,
+}
+
+impl Cubemap {
+    pub fn new(idx: u32, size: u32) -> Self {
+        Self { idx, size }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Transformation {
-    translation: Vector3<f32>,
-    scale: Vector3<f32>,
-    rotation: Vector3<f32>,
+    pub translation: Vector3<f32>,
+    pub scale: Vector3<f32>,
+    pub rotation: UnitQuaternion<f32>,
 }
 
 impl Default for Transformation {
     fn default() -> Self {
-        Self::new(
-            vector![0.0, 0.0, 0.0],
+        Self::from_parts(
+            Vector3::zeros(),
             vector![1.0, 1.0, 1.0],
-            vector![0.0, 0.0, 0.0],
+            UnitQuaternion::identity(),
         )
     }
 }
@@ -119,20 +343,186 @@ impl Transformation {
         translation: Vector3<f32>,
         scale: Vector3<f32>,
         rotation: Vector3<f32>,
+    ) -> Self {
+        Self::from_parts(translation, scale, UnitQuaternion::new(rotation))
+    }
+
+    pub fn from_parts(
+        translation: Vector3<f32>,
+        scale: Vector3<f32>,
+        rotation: UnitQuaternion<f32>,
     ) -> Self {
         Self { translation, scale, rotation }
     }
 
+    pub fn from_translation(translation: Vector3<f32>) -> Self {
+        Self { translation, ..Default::default() }
+    }
+
+    pub fn from_rotation(rotation: UnitQuaternion<f32>) -> Self {
+        Self { rotation, ..Default::default() }
+    }
+
+    pub fn from_scale(scale: Vector3<f32>) -> Self {
+        Self { scale, ..Default::default() }
+    }
+
+    pub fn from_euler_angles(roll: f32, pitch: f32, yaw: f32) -> Self {
+        Self::from_rotation(UnitQuaternion::from_euler_angles(
+            roll, pitch, yaw,
+        ))
+    }
+
+    // Places the object at `eye` with its local +Z axis facing the `target`
+    pub fn look_at(
+        eye: Point3<f32>,
+        target: Point3<f32>,
+        up: Vector3<f32>,
+    ) -> Self {
+        Self::from_parts(
+            eye.coords,
+            vector![1.0, 1.0, 1.0],
+            UnitQuaternion::face_towards(&(target - eye), &up),
+        )
+    }
+
+    // Decomposes the matrix into translation, rotation and scale.
+    // Shear and projective parts of the matrix are lost
+    pub fn from_mat(mat: &Matrix4<f32>) -> Self {
+        let translation = mat.fixed_view::<3, 1>(0, 3).into_owned();
+        let mut basis = mat.fixed_view::<3, 3>(0, 0).into_owned();
+        let mut scale = vector![
+            basis.column(0).norm(),
+            basis.column(1).norm(),
+            basis.column(2).norm()
+        ];
+        if basis.determinant() < 0.0 {
+            scale.x = -scale.x;
+        }
+
+        for i in 0..3 {
+            if scale[i].abs() > f32::EPSILON {
+                basis.column_mut(i).unscale_mut(scale[i]);
+            }
+        }
+        let rotation = UnitQuaternion::from_rotation_matrix(
+            &Rotation3::from_matrix(&basis),
+        );
+
+        Self::from_parts(translation, scale, rotation)
+    }
+
     pub fn get_mat(&self) -> Matrix4<f32> {
         let t = Matrix4::new_translation(&self.translation);
         let s = Matrix4::new_nonuniform_scaling(&self.scale);
-        let r = Matrix4::new_rotation(self.rotation);
+        let r = self.rotation.to_homogeneous();
 
         t * r * s
     }
+
+    // Exact inverse of `get_mat` for any scale
+    pub fn get_inverse_mat(&self) -> Matrix4<f32> {
+        let t = Matrix4::new_translation(&-self.translation);
+        let s =
+            Matrix4::new_nonuniform_scaling(&self.scale.map(|s| 1.0 / s));
+        let r = self.rotation.inverse().to_homogeneous();
+
+        s * r * t
+    }
+
+    pub fn get_forward(&self) -> Vector3<f32> {
+        self.rotation * Vector3::z()
+    }
+
+    pub fn get_right(&self) -> Vector3<f32> {
+        self.rotation * Vector3::x()
+    }
+
+    pub fn get_up(&self) -> Vector3<f32> {
+        self.rotation * Vector3::y()
+    }
+
+    pub fn transform_point(&self, point: &Point3<f32>) -> Point3<f32> {
+        Point3::from(self.transform_vector(&point.coords))
+            + self.translation
+    }
+
+    pub fn transform_vector(&self, vector: &Vector3<f32>) -> Vector3<f32> {
+        self.rotation * self.scale.component_mul(vector)
+    }
+
+    pub fn inverse_transform_point(
+        &self,
+        point: &Point3<f32>,
+    ) -> Point3<f32> {
+        Point3::from(
+            self.inverse_transform_vector(
+                &(point.coords - self.translation),
+            ),
+        )
+    }
+
+    pub fn inverse_transform_vector(
+        &self,
+        vector: &Vector3<f32>,
+    ) -> Vector3<f32> {
+        (self.rotation.inverse() * vector).component_div(&self.scale)
+    }
+
+    // Applies `other` first and then `self`. The result is exact only if
+    // `self` has a uniform scale, otherwise the shear is dropped
+    pub fn compose(&self, other: &Transformation) -> Self {
+        Self::from_parts(
+            self.transform_point(&Point3::from(other.translation))
+                .coords,
+            self.scale.component_mul(&other.scale),
+            self.rotation * other.rotation,
+        )
+    }
+
+    // The result is exact only if the scale is uniform: the inverse of
+    // the non-uniform scale followed by rotation has the shear, which the
+    // transformation can't hold. Use `get_inverse_mat` or
+    // `inverse_transform_point` in this case
+    pub fn inverse(&self) -> Self {
+        let rotation = self.rotation.inverse();
+        let scale = self.scale.map(|s| 1.0 / s);
+        let translation =
+            -scale.component_mul(&(rotation * self.translation));
+
+        Self::from_parts(translation, scale, rotation)
+    }
+
+    pub fn lerp(&self, other: &Transformation, k: f32) -> Self {
+        Self::from_parts(
+            self.translation.lerp(&other.translation, k),
+            self.scale.lerp(&other.scale, k),
+            self.rotation.slerp(&other.rotation, k),
+        )
+    }
+}
+
+impl Mul for Transformation {
+    type Output = Transformation;
+
+    fn mul(self, rhs: Transformation) -> Self::Output {
+        self.compose(&rhs)
+    }
+}
+
+impl From<Transformation> for Matrix4<f32> {
+    fn from(transform: Transformation) -> Self {
+        transform.get_mat()
+    }
 }
 
-#[derive(Clone, Copy)]
+impl From<Matrix4<f32>> for Transformation {
+    fn from(mat: Matrix4<f32>) -> Self {
+        Self::from_mat(&mat)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Camera2D {
     pub position: Point2<f32>,
     pub rotation: f32,
@@ -151,11 +541,18 @@ impl Camera2D {
     }
 }
 
+impl From<Camera2D> for Camera {
+    fn from(camera: Camera2D) -> Self {
+        Camera::new_2d(camera.position, camera.rotation, camera.zoom)
+    }
+}
+
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum Camera {
     Cam2D {
         position: Point2<f32>,
         rotation: f32,
+        zoom: f32,
     },
     Cam3D {
         position: Point3<f32>,
@@ -165,8 +562,12 @@ pub enum Camera {
 }
 
 impl Camera {
-    pub fn new_2d(position: Point2<f32>, rotation: f32) -> Self {
-        Self::Cam2D { position, rotation }
+    pub fn new_2d(
+        position: Point2<f32>,
+        rotation: f32,
+        zoom: f32,
+    ) -> Self {
+        Self::Cam2D { position, rotation, zoom }
     }
 
     pub fn new_3d(
@@ -184,6 +585,7 @@ impl Camera {
                 window_size.1 as f32 / 2.0
             ],
             rotation: 0.0,
+            zoom: 1.0,
         }
     }
 
@@ -191,6 +593,7 @@ impl Camera {
         Camera::Cam2D {
             position: point![0.0, 0.0],
             rotation: 0.0,
+            zoom: 1.0,
         }
     }
 
@@ -198,7 +601,7 @@ impl Camera {
         use Camera::*;
 
         match self {
-            Cam2D { position, rotation } => {
+            Cam2D { position, rotation, zoom } => {
                 let mut translation = Matrix4::identity();
                 translation[(0, 3)] = -position.x;
                 translation[(1, 3)] = -position.y;
@@ -206,14 +609,75 @@ impl Camera {
                 let rotation = Matrix4::new_rotation(Vector3::new(
                     0.0, 0.0, -rotation,
                 ));
+                let scale = Matrix4::new_nonuniform_scaling(&vector![
+                    *zoom, *zoom, 1.0
+                ]);
 
-                rotation * translation
+                scale * rotation * translation
             }
             Cam3D { position, target, up } => {
                 Matrix4::look_at_rh(position, target, up)
             }
         }
     }
+
+    // Screen positions are in window pixels with the top-left origin,
+    // like `Input::mouse_x` and `Input::mouse_y`. Depth is in NDC
+    // (-1 is the near plane, 1 is the far one)
+    pub fn unproject(
+        &self,
+        proj: &Projection,
+        window_size: (u32, u32),
+        screen_pos: Point2<f32>,
+        depth: f32,
+    ) -> Point3<f32> {
+        let inv_mat = (proj.get_mat() * self.get_mat())
+            .try_inverse()
+            .expect("Camera view-projection matrix should be invertible");
+        let ndc = point![
+            2.0 * screen_pos.x / window_size.0 as f32 - 1.0,
+            1.0 - 2.0 * screen_pos.y / window_size.1 as f32,
+            depth
+        ];
+
+        inv_mat.transform_point(&ndc)
+    }
+
+    pub fn project(
+        &self,
+        proj: &Projection,
+        window_size: (u32, u32),
+        world_pos: Point3<f32>,
+    ) -> Point3<f32> {
+        let mat = proj.get_mat() * self.get_mat();
+        let ndc = mat.transform_point(&world_pos);
+
+        point![
+            (ndc.x + 1.0) * 0.5 * window_size.0 as f32,
+            (1.0 - ndc.y) * 0.5 * window_size.1 as f32,
+            ndc.z
+        ]
+    }
+
+    pub fn screen_to_world(
+        &self,
+        proj: &Projection,
+        window_size: (u32, u32),
+        screen_pos: Point2<f32>,
+    ) -> Point2<f32> {
+        self.unproject(proj, window_size, screen_pos, 0.0).xy()
+    }
+
+    pub fn world_to_screen(
+        &self,
+        proj: &Projection,
+        window_size: (u32, u32),
+        world_pos: Point2<f32>,
+    ) -> Point2<f32> {
+        let world_pos = point![world_pos.x, world_pos.y, 0.0];
+
+        self.project(proj, window_size, world_pos).xy()
+    }
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
@@ -296,6 +760,57 @@ pub enum Material {
     BlinnPhong {
         shininess: f32,
     },
+    // Blinn-Phong lit material which also reflects the skybox
+    Reflective {
+        shininess: f32,
+        reflectivity: f32,
+    },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FogMode {
+    Linear {
+        start: f32,
+        end: f32,
+    },
+    Exponential {
+        density: f32,
+    },
+    // Exponential fog which thins out with height above `base_height`
+    Height {
+        density: f32,
+        base_height: f32,
+        falloff: f32,
+    },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Fog {
+    pub mode: FogMode,
+    pub color: Color,
+}
+
+impl Fog {
+    pub fn new(mode: FogMode, color: Color) -> Self {
+        Self { mode, color }
+    }
+
+    pub fn new_linear(color: Color, start: f32, end: f32) -> Self {
+        Self::new(FogMode::Linear { start, end }, color)
+    }
+
+    pub fn new_exponential(color: Color, density: f32) -> Self {
+        Self::new(FogMode::Exponential { density }, color)
+    }
+
+    pub fn new_height(
+        color: Color,
+        density: f32,
+        base_height: f32,
+        falloff: f32,
+    ) -> Self {
+        Self::new(FogMode::Height { density, base_height, falloff }, color)
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
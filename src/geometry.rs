@@ -12,6 +12,12 @@ pub fn rotate90(vec: &Vector2<f32>) -> Vector2<f32> {
     Vector2::new(-vec.y, vec.x)
 }
 
+pub fn rotate(vec: &Vector2<f32>, angle: f32) -> Vector2<f32> {
+    let (sin, cos) = angle.sin_cos();
+
+    Vector2::new(vec.x * cos - vec.y * sin, vec.x * sin + vec.y * cos)
+}
+
 pub fn reflect(vec: &Vector2<f32>, normal: &Vector2<f32>) -> Vector2<f32> {
     let normal = normal.normalize();
 
@@ -1,10 +1,21 @@
 pub mod audio_player;
+pub mod bounding_volume;
+pub mod camera;
 pub mod color;
 pub mod common;
 pub mod emscripten;
 pub mod geometry;
 pub mod glyph_atlas;
 pub mod input;
+pub mod map_loader;
+pub mod ray;
 pub mod renderer;
+pub mod scene;
 pub mod shapes;
+pub mod skeleton;
+pub mod sprite;
+pub mod sprite_animation;
+pub mod stroke;
+pub mod texture_atlas;
+pub mod tilemap;
 pub mod vertex_buffer;
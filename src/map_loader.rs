@@ -0,0 +1,1497 @@
+use crate::color::*;
+use crate::common::*;
+use crate::geometry::rotate;
+use crate::shapes::*;
+use crate::tilemap::*;
+use base64::Engine;
+use nalgebra::{point, Point2, Vector2};
+use roxmltree::Node;
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+use std::io::Read;
+
+const TILED_FLIP_X: u32 = 0x80000000;
+const TILED_FLIP_Y: u32 = 0x40000000;
+const TILED_FLIP_DIAGONAL: u32 = 0x20000000;
+const TILED_GID_MASK: u32 = 0x0fffffff;
+const LDTK_FLIP_X: u64 = 1;
+const LDTK_FLIP_Y: u64 = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyValue {
+    Bool(bool),
+    Int(i64),
+    Float(f32),
+    String(String),
+    Color(Color),
+}
+
+pub type Properties = HashMap<String, PropertyValue>;
+
+// Object shapes are given in the map pixels with the bottom-left map
+// corner at the origin (y-up, like the tilemap created from the map)
+#[derive(Debug, Clone)]
+pub enum MapShape {
+    Point(Point2<f32>),
+    Rectangle(Rectangle),
+    Circle(Circle),
+    Ellipse(Ellipse),
+    Polygon(Polygon),
+    Polyline(Vec<Point2<f32>>),
+}
+
+#[derive(Debug, Clone)]
+pub struct MapObject {
+    pub name: String,
+    pub class: String,
+    pub shape: MapShape,
+    pub properties: Properties,
+}
+
+#[derive(Debug, Clone)]
+pub struct MapObjectLayer {
+    pub name: String,
+    pub objects: Vec<MapObject>,
+    pub properties: Properties,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MapTile {
+    pub tileset_idx: usize,
+    pub tile: Tile,
+}
+
+#[derive(Debug, Clone)]
+pub struct MapTileLayer {
+    pub name: String,
+    pub is_visible: bool,
+    // Row by row starting from the bottom one
+    pub tiles: Vec<Option<MapTile>>,
+    pub properties: Properties,
+}
+
+#[derive(Debug, Clone)]
+pub struct MapTileset {
+    pub name: String,
+    // Image path as it's written in the map file
+    pub image: String,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub margin: u32,
+    pub spacing: u32,
+    // Animated tile id and its frames: tile ids with durations in seconds
+    pub animations: Vec<(u32, Vec<(u32, f32)>)>,
+    pub tile_properties: HashMap<u32, Properties>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MapData {
+    // In tiles
+    pub width: u32,
+    pub height: u32,
+    // In pixels
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub tilesets: Vec<MapTileset>,
+    pub tile_layers: Vec<MapTileLayer>,
+    pub object_layers: Vec<MapObjectLayer>,
+    pub properties: Properties,
+}
+
+impl MapData {
+    // Loads the Tiled xml map. External tilesets are requested by their
+    // paths from `read_file`
+    pub fn from_tmx(
+        xml: &str,
+        read_file: impl Fn(&str) -> String,
+    ) -> Self {
+        let doc =
+            roxmltree::Document::parse(xml).expect("Can't parse tmx");
+        let root = doc.root_element();
+        if root.attribute("infinite") == Some("1") {
+            panic!("Infinite Tiled maps are not supported");
+        }
+
+        let mut map = Self::new(
+            get_xml_u32(&root, "width"),
+            get_xml_u32(&root, "height"),
+            get_xml_u32(&root, "tilewidth"),
+            get_xml_u32(&root, "tileheight"),
+        );
+        map.properties = get_xml_properties(&root);
+
+        let mut first_gids = vec![];
+        for node in get_xml_children(&root, "tileset") {
+            first_gids.push(get_xml_u32(&node, "firstgid"));
+            let tileset = match node.attribute("source") {
+                Some(source) => parse_external_tileset(&read_file(source)),
+                None => parse_xml_tileset(&node),
+            };
+            map.tilesets.push(tileset);
+        }
+
+        map.parse_xml_layers(&root, &first_gids);
+
+        map
+    }
+
+    // Loads the Tiled json map. External tilesets are requested by their
+    // paths from `read_file`
+    pub fn from_tmj(
+        json: &str,
+        read_file: impl Fn(&str) -> String,
+    ) -> Self {
+        let root: Value =
+            serde_json::from_str(json).expect("Can't parse tmj");
+        if root["infinite"].as_bool() == Some(true) {
+            panic!("Infinite Tiled maps are not supported");
+        }
+
+        let mut map = Self::new(
+            get_json_u32(&root, "width"),
+            get_json_u32(&root, "height"),
+            get_json_u32(&root, "tilewidth"),
+            get_json_u32(&root, "tileheight"),
+        );
+        map.properties = get_json_properties(&root);
+
+        let mut first_gids = vec![];
+        for value in get_json_array(&root, "tilesets") {
+            first_gids.push(get_json_u32(value, "firstgid"));
+            let tileset = match value["source"].as_str() {
+                Some(source) => parse_external_tileset(&read_file(source)),
+                None => parse_json_tileset(value),
+            };
+            map.tilesets.push(tileset);
+        }
+
+        map.parse_json_layers(&root, &first_gids);
+
+        map
+    }
+
+    // Loads the level with the given identifier from the LDtk project
+    pub fn from_ldtk(json: &str, level_name: &str) -> Self {
+        let root: Value =
+            serde_json::from_str(json).expect("Can't parse ldtk");
+        let level = get_json_array(&root, "levels")
+            .iter()
+            .find(|level| level["identifier"].as_str() == Some(level_name))
+            .unwrap_or_else(|| {
+                panic!("LDtk project doesn't have level {}", level_name)
+            });
+        if level["layerInstances"].is_null() {
+            panic!("LDtk levels in separate files are not supported");
+        }
+
+        let mut tileset_ids = HashMap::new();
+        let mut tilesets = vec![];
+        for value in get_json_array(&root["defs"], "tilesets") {
+            tileset_ids.insert(value["uid"].as_i64(), tilesets.len());
+            tilesets.push(parse_ldtk_tileset(value));
+        }
+
+        // LDtk lists the layers from the top one
+        let mut layers: Vec<&Value> =
+            get_json_array(level, "layerInstances").iter().collect();
+        layers.reverse();
+
+        let grid_size = layers
+            .iter()
+            .find(|layer| layer["__type"].as_str() != Some("Entities"))
+            .map(|layer| get_json_u32(layer, "__gridSize"))
+            .unwrap_or_else(|| get_json_u32(&root, "defaultGridSize"));
+        let px_height = get_json_u32(level, "pxHei");
+
+        let mut map = Self::new(
+            get_json_u32(level, "pxWid") / grid_size,
+            px_height / grid_size,
+            grid_size,
+            grid_size,
+        );
+        map.tilesets = tilesets;
+        map.properties = get_ldtk_properties(level);
+
+        for layer in layers {
+            let name = layer["__identifier"].as_str().unwrap_or_default();
+            let is_visible = layer["visible"].as_bool().unwrap_or(true);
+            if layer["__type"].as_str() == Some("Entities") {
+                let objects = get_json_array(layer, "entityInstances")
+                    .iter()
+                    .map(|entity| parse_ldtk_entity(entity, px_height))
+                    .collect();
+                map.object_layers.push(MapObjectLayer {
+                    name: name.to_string(),
+                    objects,
+                    properties: Properties::new(),
+                });
+                continue;
+            }
+
+            let tiles = [&layer["gridTiles"], &layer["autoLayerTiles"]];
+            if tiles.iter().all(|tiles| {
+                tiles.as_array().is_none_or(|tiles| tiles.is_empty())
+            }) {
+                continue;
+            }
+
+            if get_json_u32(layer, "__gridSize") != grid_size {
+                panic!("LDtk layers with different grid sizes are not supported");
+            }
+
+            let tileset_idx = *tileset_ids
+                .get(&layer["__tilesetDefUid"].as_i64())
+                .unwrap_or_else(|| {
+                    panic!("LDtk layer {} has no tileset", name)
+                });
+            let mut map_layer = map.new_tile_layer(name, is_visible);
+            for tile in tiles.iter().filter_map(|tiles| tiles.as_array()) {
+                for tile in tile.iter() {
+                    let px = &tile["px"];
+                    let x = px[0].as_u64().unwrap_or_default() as u32
+                        / grid_size;
+                    let y = px[1].as_u64().unwrap_or_default() as u32
+                        / grid_size;
+                    if x >= map.width || y >= map.height {
+                        continue;
+                    }
+
+                    let flags = tile["f"].as_u64().unwrap_or_default();
+                    let tile = Tile {
+                        id: Some(get_json_u32(tile, "t")),
+                        rotation: 0,
+                        flip_x: flags & LDTK_FLIP_X != 0,
+                        flip_y: flags & LDTK_FLIP_Y != 0,
+                    };
+                    let idx = map.get_tile_idx(x, y);
+                    map_layer.tiles[idx] =
+                        Some(MapTile { tileset_idx, tile });
+                }
+            }
+
+            map.tile_layers.push(map_layer);
+        }
+
+        map
+    }
+
+    // Creates the tilemap with the tileset textures given in the
+    // `tilesets` order. Tile layers which use several tilesets are split
+    // into the layers with the same name
+    pub fn create_tilemap(&self, textures: &[Texture]) -> Tilemap {
+        if textures.len() != self.tilesets.len() {
+            panic!(
+                "Map has {} tilesets, but {} textures are given",
+                self.tilesets.len(),
+                textures.len()
+            );
+        }
+
+        let tile_size =
+            Vector2::new(self.tile_width as f32, self.tile_height as f32);
+        let mut tilemap = Tilemap::new(self.width, self.height, tile_size);
+
+        for (map_tileset, &tex) in self.tilesets.iter().zip(textures) {
+            let mut tileset = Tileset::new(
+                tex,
+                map_tileset.tile_width,
+                map_tileset.tile_height,
+            );
+            tileset.margin = map_tileset.margin;
+            tileset.spacing = map_tileset.spacing;
+            let tileset_idx = tilemap.add_tileset(tileset);
+
+            for (tile_id, frames) in map_tileset.animations.iter() {
+                tilemap.add_tile_animation(TileAnimation {
+                    tileset_idx,
+                    tile_id: *tile_id,
+                    frames: frames.clone(),
+                });
+            }
+        }
+
+        for layer in self.tile_layers.iter() {
+            let tileset_ids: BTreeSet<usize> = layer
+                .tiles
+                .iter()
+                .flatten()
+                .map(|tile| tile.tileset_idx)
+                .collect();
+
+            for tileset_idx in tileset_ids {
+                let layer_idx =
+                    tilemap.add_layer(&layer.name, tileset_idx);
+                tilemap.set_layer_visible(layer_idx, layer.is_visible);
+
+                for (idx, tile) in layer.tiles.iter().enumerate() {
+                    let Some(tile) = tile
+                        .filter(|tile| tile.tileset_idx == tileset_idx)
+                    else {
+                        continue;
+                    };
+
+                    let x = idx as u32 % self.width;
+                    let y = idx as u32 / self.width;
+                    tilemap.set_tile(layer_idx, x, y, tile.tile);
+                }
+            }
+        }
+
+        tilemap
+    }
+
+    pub fn find_tile_layer(&self, name: &str) -> Option<&MapTileLayer> {
+        self.tile_layers.iter().find(|layer| layer.name == name)
+    }
+
+    pub fn find_object_layer(
+        &self,
+        name: &str,
+    ) -> Option<&MapObjectLayer> {
+        self.object_layers.iter().find(|layer| layer.name == name)
+    }
+
+    fn new(
+        width: u32,
+        height: u32,
+        tile_width: u32,
+        tile_height: u32,
+    ) -> Self {
+        Self {
+            width,
+            height,
+            tile_width,
+            tile_height,
+            tilesets: vec![],
+            tile_layers: vec![],
+            object_layers: vec![],
+            properties: Properties::new(),
+        }
+    }
+
+    fn new_tile_layer(
+        &self,
+        name: &str,
+        is_visible: bool,
+    ) -> MapTileLayer {
+        MapTileLayer {
+            name: name.to_string(),
+            is_visible,
+            tiles: vec![None; (self.width * self.height) as usize],
+            properties: Properties::new(),
+        }
+    }
+
+    // Map files list the rows from the top one
+    fn get_tile_idx(&self, x: u32, y_down: u32) -> usize {
+        ((self.height - 1 - y_down) * self.width + x) as usize
+    }
+
+    fn get_px_height(&self) -> f32 {
+        (self.height * self.tile_height) as f32
+    }
+
+    fn set_tiled_gids(
+        &self,
+        layer: &mut MapTileLayer,
+        gids: &[u32],
+        first_gids: &[u32],
+    ) {
+        if gids.len() != layer.tiles.len() {
+            panic!(
+                "Tiled layer {} has {} tiles, expected {}",
+                layer.name,
+                gids.len(),
+                layer.tiles.len()
+            );
+        }
+
+        for (i, &gid) in gids.iter().enumerate() {
+            let (x, y) = (i as u32 % self.width, i as u32 / self.width);
+            layer.tiles[self.get_tile_idx(x, y)] =
+                get_tiled_tile(gid, first_gids);
+        }
+    }
+
+    fn parse_xml_layers(&mut self, parent: &Node, first_gids: &[u32]) {
+        for node in parent.children().filter(|n| n.is_element()) {
+            let name = node.attribute("name").unwrap_or_default();
+            let is_visible = node.attribute("visible") != Some("0");
+            match node.tag_name().name() {
+                "layer" => {
+                    let data = get_xml_children(&node, "data")
+                        .next()
+                        .expect("Tiled layer should have data");
+                    let gids = match data.attribute("encoding") {
+                        Some(encoding) => decode_tiled_data(
+                            data.text().unwrap_or_default(),
+                            encoding,
+                            data.attribute("compression"),
+                        ),
+                        None => get_xml_children(&data, "tile")
+                            .map(|tile| {
+                                tile.attribute("gid")
+                                    .map_or(0, |gid| gid.parse().unwrap())
+                            })
+                            .collect(),
+                    };
+
+                    let mut layer = self.new_tile_layer(name, is_visible);
+                    layer.properties = get_xml_properties(&node);
+                    self.set_tiled_gids(&mut layer, &gids, first_gids);
+                    self.tile_layers.push(layer);
+                }
+                "objectgroup" => {
+                    let px_height = self.get_px_height();
+                    let objects = get_xml_children(&node, "object")
+                        .map(|object| parse_xml_object(&object, px_height))
+                        .collect();
+                    self.object_layers.push(MapObjectLayer {
+                        name: name.to_string(),
+                        objects,
+                        properties: get_xml_properties(&node),
+                    });
+                }
+                "group" => self.parse_xml_layers(&node, first_gids),
+                _ => {}
+            }
+        }
+    }
+
+    fn parse_json_layers(&mut self, parent: &Value, first_gids: &[u32]) {
+        for value in get_json_array(parent, "layers") {
+            let name = value["name"].as_str().unwrap_or_default();
+            let is_visible = value["visible"].as_bool().unwrap_or(true);
+            match value["type"].as_str().unwrap_or_default() {
+                "tilelayer" => {
+                    let gids = match &value["data"] {
+                        Value::String(data) => decode_tiled_data(
+                            data,
+                            value["encoding"].as_str().unwrap_or("base64"),
+                            value["compression"]
+                                .as_str()
+                                .filter(|c| !c.is_empty()),
+                        ),
+                        Value::Array(data) => data
+                            .iter()
+                            .map(|gid| {
+                                gid.as_u64().unwrap_or_default() as u32
+                            })
+                            .collect(),
+                        _ => panic!("Tiled layer should have data"),
+                    };
+
+                    let mut layer = self.new_tile_layer(name, is_visible);
+                    layer.properties = get_json_properties(value);
+                    self.set_tiled_gids(&mut layer, &gids, first_gids);
+                    self.tile_layers.push(layer);
+                }
+                "objectgroup" => {
+                    let px_height = self.get_px_height();
+                    let objects = get_json_array(value, "objects")
+                        .iter()
+                        .map(|object| parse_json_object(object, px_height))
+                        .collect();
+                    self.object_layers.push(MapObjectLayer {
+                        name: name.to_string(),
+                        objects,
+                        properties: get_json_properties(value),
+                    });
+                }
+                "group" => self.parse_json_layers(value, first_gids),
+                _ => {}
+            }
+        }
+    }
+}
+
+enum TiledObjectKind {
+    Rectangle,
+    Ellipse,
+    Point,
+    // Tile objects are placed by their bottom-left corner
+    Tile,
+    Polygon(Vec<Point2<f32>>),
+    Polyline(Vec<Point2<f32>>),
+}
+
+// Tiled object geometry: position and size in the y-down map pixels,
+// clockwise rotation in degrees around the position
+struct TiledObject {
+    position: Point2<f32>,
+    size: Vector2<f32>,
+    rotation: f32,
+    kind: TiledObjectKind,
+}
+
+impl TiledObject {
+    fn get_shape(&self, px_height: f32) -> MapShape {
+        let origin = point![self.position.x, px_height - self.position.y];
+        let angle = -self.rotation.to_radians();
+        let to_world = |offset: Vector2<f32>| {
+            origin + rotate(&Vector2::new(offset.x, -offset.y), angle)
+        };
+        let (w, h) = (self.size.x, self.size.y);
+
+        match &self.kind {
+            TiledObjectKind::Point => MapShape::Point(origin),
+            TiledObjectKind::Ellipse => {
+                let center = to_world(self.size * 0.5);
+                if w == h {
+                    MapShape::Circle(Circle::new(center, w * 0.5))
+                } else {
+                    MapShape::Ellipse(Ellipse::new(
+                        center,
+                        self.size * 0.5,
+                        angle,
+                    ))
+                }
+            }
+            TiledObjectKind::Rectangle | TiledObjectKind::Tile => {
+                let top = match self.kind {
+                    TiledObjectKind::Tile => -h,
+                    _ => 0.0,
+                };
+                let corners = [
+                    Vector2::new(0.0, top + h),
+                    Vector2::new(w, top + h),
+                    Vector2::new(w, top),
+                    Vector2::new(0.0, top),
+                ]
+                .map(to_world);
+
+                if angle == 0.0 {
+                    MapShape::Rectangle(Rectangle::new(
+                        corners[0], corners[2],
+                    ))
+                } else {
+                    MapShape::Polygon(Polygon::new(corners.to_vec()))
+                }
+            }
+            TiledObjectKind::Polygon(points) => {
+                MapShape::Polygon(Polygon::new(
+                    points.iter().map(|p| to_world(p.coords)).collect(),
+                ))
+            }
+            TiledObjectKind::Polyline(points) => MapShape::Polyline(
+                points.iter().map(|p| to_world(p.coords)).collect(),
+            ),
+        }
+    }
+}
+
+fn get_tiled_tile(gid: u32, first_gids: &[u32]) -> Option<MapTile> {
+    let id = gid & TILED_GID_MASK;
+    if id == 0 {
+        return None;
+    }
+
+    let tileset_idx = first_gids.iter().rposition(|&first| first <= id)?;
+    let (flip_x, flip_y) =
+        (gid & TILED_FLIP_X != 0, gid & TILED_FLIP_Y != 0);
+
+    // The diagonal flip is the quarter turn followed by the vertical flip
+    let tile = if gid & TILED_FLIP_DIAGONAL != 0 {
+        Tile {
+            id: Some(id - first_gids[tileset_idx]),
+            rotation: 1,
+            flip_x,
+            flip_y: !flip_y,
+        }
+    } else {
+        Tile {
+            id: Some(id - first_gids[tileset_idx]),
+            rotation: 0,
+            flip_x,
+            flip_y,
+        }
+    };
+
+    Some(MapTile { tileset_idx, tile })
+}
+
+fn decode_tiled_data(
+    data: &str,
+    encoding: &str,
+    compression: Option<&str>,
+) -> Vec<u32> {
+    if encoding == "csv" {
+        return data
+            .split(',')
+            .map(|gid| gid.trim())
+            .filter(|gid| !gid.is_empty())
+            .map(|gid| gid.parse().expect("Bad Tiled csv tile"))
+            .collect();
+    } else if encoding != "base64" {
+        panic!("Unknown Tiled data encoding: {}", encoding);
+    }
+
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(data.trim())
+        .expect("Bad Tiled base64 data");
+    let mut decoded = vec![];
+    let result = match compression {
+        None => Ok(decoded.len()),
+        Some("zlib") => flate2::read::ZlibDecoder::new(&bytes[..])
+            .read_to_end(&mut decoded),
+        Some("gzip") => flate2::read::GzDecoder::new(&bytes[..])
+            .read_to_end(&mut decoded),
+        Some(compression) => {
+            panic!("Unsupported Tiled data compression: {}", compression)
+        }
+    };
+    result.expect("Can't decompress Tiled data");
+
+    let bytes = if compression.is_none() {
+        bytes
+    } else {
+        decoded
+    };
+    bytes
+        .chunks_exact(4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+fn parse_external_tileset(src: &str) -> MapTileset {
+    if src.trim_start().starts_with('<') {
+        let doc =
+            roxmltree::Document::parse(src).expect("Can't parse tsx");
+
+        parse_xml_tileset(&doc.root_element())
+    } else {
+        let value: Value =
+            serde_json::from_str(src).expect("Can't parse tsj");
+
+        parse_json_tileset(&value)
+    }
+}
+
+fn parse_xml_tileset(node: &Node) -> MapTileset {
+    let image = get_xml_children(node, "image")
+        .next()
+        .and_then(|image| image.attribute("source"))
+        .expect("Tilesets without a single image are not supported");
+
+    let mut animations = vec![];
+    let mut tile_properties = HashMap::new();
+    for tile in get_xml_children(node, "tile") {
+        let id = get_xml_u32(&tile, "id");
+        if let Some(animation) =
+            get_xml_children(&tile, "animation").next()
+        {
+            let frames = get_xml_children(&animation, "frame")
+                .map(|frame| {
+                    (
+                        get_xml_u32(&frame, "tileid"),
+                        get_xml_u32(&frame, "duration") as f32 / 1000.0,
+                    )
+                })
+                .collect();
+            animations.push((id, frames));
+        }
+
+        let properties = get_xml_properties(&tile);
+        if !properties.is_empty() {
+            tile_properties.insert(id, properties);
+        }
+    }
+
+    let get_optional_u32 =
+        |key| node.attribute(key).map_or(0, |v| v.parse().unwrap());
+
+    MapTileset {
+        name: node.attribute("name").unwrap_or_default().to_string(),
+        image: image.to_string(),
+        tile_width: get_xml_u32(node, "tilewidth"),
+        tile_height: get_xml_u32(node, "tileheight"),
+        margin: get_optional_u32("margin"),
+        spacing: get_optional_u32("spacing"),
+        animations,
+        tile_properties,
+    }
+}
+
+fn parse_json_tileset(value: &Value) -> MapTileset {
+    let image = value["image"]
+        .as_str()
+        .expect("Tilesets without a single image are not supported");
+
+    let mut animations = vec![];
+    let mut tile_properties = HashMap::new();
+    for tile in value["tiles"].as_array().into_iter().flatten() {
+        let id = get_json_u32(tile, "id");
+        if let Some(animation) = tile["animation"].as_array() {
+            let frames = animation
+                .iter()
+                .map(|frame| {
+                    (
+                        get_json_u32(frame, "tileid"),
+                        get_json_u32(frame, "duration") as f32 / 1000.0,
+                    )
+                })
+                .collect();
+            animations.push((id, frames));
+        }
+
+        let properties = get_json_properties(tile);
+        if !properties.is_empty() {
+            tile_properties.insert(id, properties);
+        }
+    }
+
+    MapTileset {
+        name: value["name"].as_str().unwrap_or_default().to_string(),
+        image: image.to_string(),
+        tile_width: get_json_u32(value, "tilewidth"),
+        tile_height: get_json_u32(value, "tileheight"),
+        margin: value["margin"].as_u64().unwrap_or_default() as u32,
+        spacing: value["spacing"].as_u64().unwrap_or_default() as u32,
+        animations,
+        tile_properties,
+    }
+}
+
+fn parse_ldtk_tileset(value: &Value) -> MapTileset {
+    let grid_size = get_json_u32(value, "tileGridSize");
+    let mut tile_properties = HashMap::new();
+    for data in value["customData"].as_array().into_iter().flatten() {
+        let mut properties = Properties::new();
+        properties.insert(
+            "data".to_string(),
+            PropertyValue::String(
+                data["data"].as_str().unwrap_or_default().to_string(),
+            ),
+        );
+        tile_properties.insert(get_json_u32(data, "tileId"), properties);
+    }
+
+    MapTileset {
+        name: value["identifier"].as_str().unwrap_or_default().to_string(),
+        image: value["relPath"].as_str().unwrap_or_default().to_string(),
+        tile_width: grid_size,
+        tile_height: grid_size,
+        margin: value["padding"].as_u64().unwrap_or_default() as u32,
+        spacing: value["spacing"].as_u64().unwrap_or_default() as u32,
+        animations: vec![],
+        tile_properties,
+    }
+}
+
+fn parse_xml_object(node: &Node, px_height: f32) -> MapObject {
+    let get_f32 = |key| {
+        node.attribute(key)
+            .map_or(0.0, |v: &str| v.parse::<f32>().unwrap())
+    };
+    let parse_points = |node: Node| {
+        node.attribute("points")
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(|p| {
+                let (x, y) = p.split_once(',').expect("Bad Tiled point");
+                point![x.parse().unwrap(), y.parse().unwrap()]
+            })
+            .collect()
+    };
+
+    let mut kind = match node.attribute("gid") {
+        Some(_) => TiledObjectKind::Tile,
+        None => TiledObjectKind::Rectangle,
+    };
+    for child in node.children() {
+        kind = match child.tag_name().name() {
+            "ellipse" => TiledObjectKind::Ellipse,
+            "point" => TiledObjectKind::Point,
+            "polygon" => TiledObjectKind::Polygon(parse_points(child)),
+            "polyline" => TiledObjectKind::Polyline(parse_points(child)),
+            _ => continue,
+        };
+    }
+
+    let object = TiledObject {
+        position: point![get_f32("x"), get_f32("y")],
+        size: Vector2::new(get_f32("width"), get_f32("height")),
+        rotation: get_f32("rotation"),
+        kind,
+    };
+
+    MapObject {
+        name: node.attribute("name").unwrap_or_default().to_string(),
+        class: node
+            .attribute("class")
+            .or(node.attribute("type"))
+            .unwrap_or_default()
+            .to_string(),
+        shape: object.get_shape(px_height),
+        properties: get_xml_properties(node),
+    }
+}
+
+fn parse_json_object(value: &Value, px_height: f32) -> MapObject {
+    let get_f32 = |value: &Value, key| {
+        value[key].as_f64().unwrap_or_default() as f32
+    };
+    let parse_points = |points: &Value| {
+        points
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|p| point![get_f32(p, "x"), get_f32(p, "y")])
+            .collect()
+    };
+
+    let kind = if value["ellipse"].as_bool() == Some(true) {
+        TiledObjectKind::Ellipse
+    } else if value["point"].as_bool() == Some(true) {
+        TiledObjectKind::Point
+    } else if value["polygon"].is_array() {
+        TiledObjectKind::Polygon(parse_points(&value["polygon"]))
+    } else if value["polyline"].is_array() {
+        TiledObjectKind::Polyline(parse_points(&value["polyline"]))
+    } else if value["gid"].is_u64() {
+        TiledObjectKind::Tile
+    } else {
+        TiledObjectKind::Rectangle
+    };
+
+    let object = TiledObject {
+        position: point![get_f32(value, "x"), get_f32(value, "y")],
+        size: Vector2::new(
+            get_f32(value, "width"),
+            get_f32(value, "height"),
+        ),
+        rotation: get_f32(value, "rotation"),
+        kind,
+    };
+
+    MapObject {
+        name: value["name"].as_str().unwrap_or_default().to_string(),
+        class: value["class"]
+            .as_str()
+            .or(value["type"].as_str())
+            .unwrap_or_default()
+            .to_string(),
+        shape: object.get_shape(px_height),
+        properties: get_json_properties(value),
+    }
+}
+
+fn parse_ldtk_entity(value: &Value, px_height: u32) -> MapObject {
+    let get_f32 =
+        |value: &Value| value.as_f64().unwrap_or_default() as f32;
+    let size =
+        Vector2::new(get_f32(&value["width"]), get_f32(&value["height"]));
+    let pivot = Vector2::new(
+        get_f32(&value["__pivot"][0]),
+        get_f32(&value["__pivot"][1]),
+    );
+    let position =
+        point![get_f32(&value["px"][0]), get_f32(&value["px"][1])];
+
+    // LDtk entity position is its pivot in the y-down level pixels
+    let top_left = position - size.component_mul(&pivot);
+    let bot_left =
+        point![top_left.x, px_height as f32 - top_left.y - size.y];
+    let name = value["__identifier"].as_str().unwrap_or_default();
+
+    MapObject {
+        name: name.to_string(),
+        class: String::new(),
+        shape: MapShape::Rectangle(Rectangle::from_bot_left(
+            bot_left, size,
+        )),
+        properties: get_ldtk_properties(value),
+    }
+}
+
+fn get_xml_children<'a, 'input>(
+    node: &Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(move |n| n.has_tag_name(name))
+}
+
+fn get_xml_u32(node: &Node, key: &str) -> u32 {
+    node.attribute(key)
+        .and_then(|v| v.parse().ok())
+        .unwrap_or_else(|| {
+            panic!(
+                "Tiled <{}> should have the `{}` attribute",
+                node.tag_name().name(),
+                key
+            )
+        })
+}
+
+fn get_xml_properties(node: &Node) -> Properties {
+    let mut properties = Properties::new();
+    let Some(parent) = get_xml_children(node, "properties").next() else {
+        return properties;
+    };
+
+    for property in get_xml_children(&parent, "property") {
+        let name = property.attribute("name").unwrap_or_default();
+        // Multiline strings are stored as the element text
+        let value = property
+            .attribute("value")
+            .or(property.text())
+            .unwrap_or_default();
+        let ty = property.attribute("type").unwrap_or("string");
+        properties
+            .insert(name.to_string(), parse_tiled_property(ty, value));
+    }
+
+    properties
+}
+
+fn get_json_properties(value: &Value) -> Properties {
+    let mut properties = Properties::new();
+    for property in value["properties"].as_array().into_iter().flatten() {
+        let name = property["name"].as_str().unwrap_or_default();
+        let ty = property["type"].as_str().unwrap_or("string");
+        let value = match &property["value"] {
+            Value::String(value) => value.clone(),
+            value => value.to_string(),
+        };
+        properties
+            .insert(name.to_string(), parse_tiled_property(ty, &value));
+    }
+
+    properties
+}
+
+fn get_ldtk_properties(value: &Value) -> Properties {
+    let mut properties = Properties::new();
+    for field in value["fieldInstances"].as_array().into_iter().flatten() {
+        let name = field["__identifier"].as_str().unwrap_or_default();
+        let value = &field["__value"];
+        let property = match field["__type"].as_str().unwrap_or_default() {
+            "Int" => value.as_i64().map(PropertyValue::Int),
+            "Float" => {
+                value.as_f64().map(|v| PropertyValue::Float(v as f32))
+            }
+            "Bool" => value.as_bool().map(PropertyValue::Bool),
+            "Color" => value
+                .as_str()
+                .map(|v| PropertyValue::Color(parse_hex_color(v))),
+            _ => None,
+        };
+        let property = property.unwrap_or_else(|| match value {
+            Value::String(value) => PropertyValue::String(value.clone()),
+            value => PropertyValue::String(value.to_string()),
+        });
+        properties.insert(name.to_string(), property);
+    }
+
+    properties
+}
+
+fn parse_tiled_property(ty: &str, value: &str) -> PropertyValue {
+    match ty {
+        "bool" => PropertyValue::Bool(value == "true"),
+        "int" | "object" => {
+            PropertyValue::Int(value.parse().unwrap_or_default())
+        }
+        "float" => PropertyValue::Float(value.parse().unwrap_or_default()),
+        "color" if !value.is_empty() => {
+            PropertyValue::Color(parse_hex_color(value))
+        }
+        _ => PropertyValue::String(value.to_string()),
+    }
+}
+
+// Parses #RRGGBB and Tiled #AARRGGBB colors
+fn parse_hex_color(hex: &str) -> Color {
+    let hex = hex.trim_start_matches('#');
+    let value = u32::from_str_radix(hex, 16).expect("Bad hex color");
+    let get_channel =
+        |shift: u32| ((value >> shift) & 0xff) as f32 / 255.0;
+    let a = if hex.len() == 8 { get_channel(24) } else { 1.0 };
+
+    Color::new(get_channel(16), get_channel(8), get_channel(0), a)
+}
+
+fn get_json_u32(value: &Value, key: &str) -> u32 {
+    value[key].as_u64().unwrap_or_else(|| {
+        panic!("Map json should have the `{}` field", key)
+    }) as u32
+}
+
+fn get_json_array<'a>(value: &'a Value, key: &str) -> &'a Vec<Value> {
+    value[key].as_array().unwrap_or_else(|| {
+        panic!("Map json should have the `{}` array", key)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const FLIP_X: u32 = TILED_FLIP_X;
+    const FLIP_Y: u32 = TILED_FLIP_Y;
+    const FLIP_D: u32 = TILED_FLIP_DIAGONAL;
+
+    // 3x2 map, the second tileset starts at gid 5. Rows are listed from
+    // the top one
+    const TILED_GIDS: [u32; 6] =
+        [1, 0, 6, FLIP_X | 2, FLIP_Y | 3, FLIP_D | 4];
+
+    fn get_tiled_csv() -> String {
+        TILED_GIDS.map(|gid| gid.to_string()).join(",")
+    }
+
+    fn get_tiled_base64(compression: Option<&str>) -> String {
+        let bytes: Vec<u8> = TILED_GIDS
+            .iter()
+            .flat_map(|gid| gid.to_le_bytes())
+            .collect();
+        let bytes = match compression {
+            None => bytes,
+            Some("zlib") => {
+                let mut encoder = flate2::write::ZlibEncoder::new(
+                    vec![],
+                    flate2::Compression::default(),
+                );
+                encoder.write_all(&bytes).unwrap();
+                encoder.finish().unwrap()
+            }
+            Some("gzip") => {
+                let mut encoder = flate2::write::GzEncoder::new(
+                    vec![],
+                    flate2::Compression::default(),
+                );
+                encoder.write_all(&bytes).unwrap();
+                encoder.finish().unwrap()
+            }
+            Some(compression) => panic!("Unknown {}", compression),
+        };
+
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    }
+
+    fn get_tmx(data: &str) -> String {
+        format!(
+            r##"<?xml version="1.0" encoding="UTF-8"?>
+<map width="3" height="2" tilewidth="16" tileheight="16" infinite="0">
+ <properties>
+  <property name="gravity" type="float" value="9.5"/>
+ </properties>
+ <tileset firstgid="1" name="ground" tilewidth="16" tileheight="16">
+  <image source="ground.png" width="32" height="32"/>
+  <tile id="1">
+   <animation>
+    <frame tileid="1" duration="100"/>
+    <frame tileid="2" duration="250"/>
+   </animation>
+  </tile>
+ </tileset>
+ <tileset firstgid="5" source="props.tsx"/>
+ <group name="level">
+  <layer name="ground" width="3" height="2">
+   <properties>
+    <property name="solid" type="bool" value="true"/>
+   </properties>
+   {}
+  </layer>
+ </group>
+ <objectgroup name="objects">
+  <object id="1" name="spawn" type="player" x="16" y="8" width="8" height="4">
+   <properties>
+    <property name="hp" type="int" value="3"/>
+    <property name="tint" type="color" value="#80ff0000"/>
+   </properties>
+  </object>
+  <object id="2" name="coin" x="4" y="10"><point/></object>
+  <object id="3" name="chest" gid="5" x="0" y="32" width="16" height="16"/>
+  <object id="4" name="door" x="0" y="0" width="10" height="20" rotation="90"/>
+  <object id="5" name="lamp" x="8" y="4" width="6" height="6"><ellipse/></object>
+  <object id="6" name="area" x="10" y="2"><polygon points="0,0 4,0 0,6"/></object>
+ </objectgroup>
+</map>"##,
+            data
+        )
+    }
+
+    fn get_props_tsx() -> String {
+        r#"<tileset name="props" tilewidth="16" tileheight="16" margin="1" spacing="2">
+ <image source="props.png" width="36" height="36"/>
+</tileset>"#
+            .to_string()
+    }
+
+    fn assert_points_eq(a: &[Point2<f32>], b: &[Point2<f32>]) {
+        assert_eq!(a.len(), b.len(), "{:?} != {:?}", a, b);
+        for (a, b) in a.iter().zip(b) {
+            assert!((a - b).norm() < 1e-4, "{:?} != {:?}", a, b);
+        }
+    }
+
+    fn assert_rect_eq(
+        shape: &MapShape,
+        min: Point2<f32>,
+        max: Point2<f32>,
+    ) {
+        let MapShape::Rectangle(rect) = shape else {
+            panic!("Expecting rectangle, got {:?}", shape);
+        };
+        assert_points_eq(
+            &[rect.get_bot_left(), rect.get_top_right()],
+            &[min, max],
+        );
+    }
+
+    fn assert_tiled_tiles(map: &MapData) {
+        assert_eq!(map.tile_layers.len(), 1);
+        let tiles = &map.tile_layers[0].tiles;
+        let tile = |tileset_idx, id, rotation, flip_x, flip_y| {
+            Some(MapTile {
+                tileset_idx,
+                tile: Tile { id: Some(id), rotation, flip_x, flip_y },
+            })
+        };
+
+        // Bottom row first
+        assert_eq!(tiles[0], tile(0, 1, 0, true, false));
+        assert_eq!(tiles[1], tile(0, 2, 0, false, true));
+        assert_eq!(tiles[2], tile(0, 3, 1, false, true));
+        assert_eq!(tiles[3], tile(0, 0, 0, false, false));
+        assert_eq!(tiles[4], None);
+        assert_eq!(tiles[5], tile(1, 1, 0, false, false));
+    }
+
+    #[test]
+    fn tiled_gid_flips() {
+        let first_gids = [1, 5];
+        let get_tile = |gid| get_tiled_tile(gid, &first_gids).unwrap();
+
+        assert_eq!(get_tiled_tile(0, &first_gids), None);
+        assert_eq!(get_tiled_tile(FLIP_X, &first_gids), None);
+
+        let tile = get_tile(FLIP_X | FLIP_Y | 7);
+        assert_eq!(tile.tileset_idx, 1);
+        assert_eq!(tile.tile.id, Some(2));
+        assert_eq!(tile.tile.rotation, 0);
+        assert!(tile.tile.flip_x && tile.tile.flip_y);
+
+        // Diagonal flip is the quarter turn with the inverted y flip
+        let tile = get_tile(FLIP_D | 1).tile;
+        assert_eq!(
+            (tile.rotation, tile.flip_x, tile.flip_y),
+            (1, false, true)
+        );
+        let tile = get_tile(FLIP_D | FLIP_Y | 1).tile;
+        assert_eq!(
+            (tile.rotation, tile.flip_x, tile.flip_y),
+            (1, false, false)
+        );
+        let tile = get_tile(FLIP_D | FLIP_X | 1).tile;
+        assert_eq!(
+            (tile.rotation, tile.flip_x, tile.flip_y),
+            (1, true, true)
+        );
+    }
+
+    #[test]
+    fn tiled_data_decoding() {
+        let csv = format!(" \n{}\n", get_tiled_csv());
+        assert_eq!(decode_tiled_data(&csv, "csv", None), TILED_GIDS);
+
+        for compression in [None, Some("zlib"), Some("gzip")] {
+            let data = get_tiled_base64(compression);
+            assert_eq!(
+                decode_tiled_data(&data, "base64", compression),
+                TILED_GIDS,
+                "{:?} compression",
+                compression
+            );
+        }
+    }
+
+    #[test]
+    fn tmx_tiles_and_tilesets() {
+        let data = format!(
+            r#"<data encoding="base64" compression="zlib">{}</data>"#,
+            get_tiled_base64(Some("zlib"))
+        );
+        let map = MapData::from_tmx(&get_tmx(&data), |path| {
+            assert_eq!(path, "props.tsx");
+            get_props_tsx()
+        });
+
+        assert_eq!((map.width, map.height), (3, 2));
+        assert_eq!(map.properties["gravity"], PropertyValue::Float(9.5));
+        assert_tiled_tiles(&map);
+        assert_eq!(
+            map.find_tile_layer("ground").unwrap().properties["solid"],
+            PropertyValue::Bool(true)
+        );
+
+        assert_eq!(map.tilesets.len(), 2);
+        assert_eq!(map.tilesets[0].image, "ground.png");
+        assert_eq!(
+            map.tilesets[0].animations,
+            vec![(1, vec![(1, 0.1), (2, 0.25)])]
+        );
+        assert_eq!(map.tilesets[1].name, "props");
+        assert_eq!(
+            (map.tilesets[1].margin, map.tilesets[1].spacing),
+            (1, 2)
+        );
+
+        let csv =
+            format!(r#"<data encoding="csv">{}</data>"#, get_tiled_csv());
+        let xml_tiles: String = TILED_GIDS
+            .iter()
+            .map(|gid| format!(r#"<tile gid="{}"/>"#, gid))
+            .collect();
+        let xml = format!("<data>{}</data>", xml_tiles);
+        for data in [csv, xml] {
+            let map =
+                MapData::from_tmx(&get_tmx(&data), |_| get_props_tsx());
+            assert_tiled_tiles(&map);
+        }
+    }
+
+    #[test]
+    fn tmx_objects() {
+        let data =
+            format!(r#"<data encoding="csv">{}</data>"#, get_tiled_csv());
+        let map = MapData::from_tmx(&get_tmx(&data), |_| get_props_tsx());
+        let objects = &map.find_object_layer("objects").unwrap().objects;
+        assert_eq!(objects.len(), 6);
+
+        // Map is 32 pixels high, objects are flipped to y-up
+        let spawn = &objects[0];
+        assert_eq!(
+            (spawn.name.as_str(), spawn.class.as_str()),
+            ("spawn", "player")
+        );
+        assert_rect_eq(
+            &spawn.shape,
+            point![16.0, 20.0],
+            point![24.0, 24.0],
+        );
+        assert_eq!(spawn.properties["hp"], PropertyValue::Int(3));
+        assert_eq!(
+            spawn.properties["tint"],
+            PropertyValue::Color(Color::new(1.0, 0.0, 0.0, 128.0 / 255.0))
+        );
+
+        let MapShape::Point(coin) = objects[1].shape else {
+            panic!("Coin should be a point");
+        };
+        assert_points_eq(&[coin], &[point![4.0, 22.0]]);
+
+        // Tile objects are placed by their bottom-left corner
+        assert_rect_eq(
+            &objects[2].shape,
+            point![0.0, 0.0],
+            point![16.0, 16.0],
+        );
+
+        // Rotated clockwise around the top-left corner
+        let MapShape::Polygon(door) = &objects[3].shape else {
+            panic!("Rotated rectangle should be a polygon");
+        };
+        assert_points_eq(
+            &door.points,
+            &[
+                point![-20.0, 32.0],
+                point![-20.0, 22.0],
+                point![0.0, 22.0],
+                point![0.0, 32.0],
+            ],
+        );
+
+        let MapShape::Circle(lamp) = objects[4].shape else {
+            panic!("Ellipse with the equal sides should be a circle");
+        };
+        assert_points_eq(&[lamp.center], &[point![11.0, 25.0]]);
+        assert_eq!(lamp.radius, 3.0);
+
+        let MapShape::Polygon(area) = &objects[5].shape else {
+            panic!("Area should be a polygon");
+        };
+        assert_points_eq(
+            &area.points,
+            &[point![10.0, 30.0], point![14.0, 30.0], point![10.0, 24.0]],
+        );
+    }
+
+    #[test]
+    fn tmj_tiles_and_objects() {
+        let tmj = format!(
+            r#"{{
+  "width": 3, "height": 2, "tilewidth": 16, "tileheight": 16,
+  "infinite": false,
+  "tilesets": [
+    {{"firstgid": 1, "name": "ground", "image": "ground.png",
+      "tilewidth": 16, "tileheight": 16,
+      "tiles": [{{"id": 3, "properties": [
+        {{"name": "kind", "type": "string", "value": "spike"}}]}}]}},
+    {{"firstgid": 5, "source": "props.tsj"}}
+  ],
+  "layers": [
+    {{"type": "group", "name": "level", "layers": [
+      {{"type": "tilelayer", "name": "ground", "encoding": "base64",
+        "compression": "gzip", "data": "{}"}}
+    ]}},
+    {{"type": "objectgroup", "name": "objects", "objects": [
+      {{"name": "spawn", "class": "player", "x": 16, "y": 8,
+        "width": 8, "height": 4, "rotation": 0,
+        "properties": [{{"name": "hp", "type": "int", "value": 3}}]}},
+      {{"name": "path", "x": 2, "y": 2, "rotation": 0,
+        "polyline": [{{"x": 0, "y": 0}}, {{"x": 6, "y": 4}}]}}
+    ]}}
+  ]
+}}"#,
+            get_tiled_base64(Some("gzip"))
+        );
+        let tsj = r#"{"name": "props", "image": "props.png",
+            "tilewidth": 16, "tileheight": 16}"#;
+        let map = MapData::from_tmj(&tmj, |path| {
+            assert_eq!(path, "props.tsj");
+            tsj.to_string()
+        });
+
+        assert_tiled_tiles(&map);
+        assert_eq!(map.tilesets[1].image, "props.png");
+        assert_eq!(
+            map.tilesets[0].tile_properties[&3]["kind"],
+            PropertyValue::String("spike".to_string())
+        );
+
+        let objects = &map.find_object_layer("objects").unwrap().objects;
+        assert_eq!(objects[0].class, "player");
+        assert_eq!(objects[0].properties["hp"], PropertyValue::Int(3));
+        assert_rect_eq(
+            &objects[0].shape,
+            point![16.0, 20.0],
+            point![24.0, 24.0],
+        );
+        let MapShape::Polyline(path) = &objects[1].shape else {
+            panic!("Path should be a polyline");
+        };
+        assert_points_eq(path, &[point![2.0, 30.0], point![8.0, 26.0]]);
+
+        let csv_tmj = tmj.replace(
+            &format!(
+                r#""compression": "gzip", "data": "{}""#,
+                get_tiled_base64(Some("gzip"))
+            ),
+            &format!(r#""data": [{}]"#, get_tiled_csv()),
+        );
+        assert_ne!(csv_tmj, tmj);
+        assert_tiled_tiles(&MapData::from_tmj(&csv_tmj, |_| {
+            tsj.to_string()
+        }));
+    }
+
+    #[test]
+    fn ldtk_level() {
+        let ldtk = r##"{
+  "defaultGridSize": 16,
+  "defs": {"tilesets": [
+    {"uid": 7, "identifier": "Ground", "relPath": "ground.png",
+     "tileGridSize": 16, "padding": 0, "spacing": 0,
+     "customData": [{"tileId": 2, "data": "water"}]}
+  ]},
+  "levels": [
+    {"identifier": "Other", "pxWid": 16, "pxHei": 16,
+     "layerInstances": []},
+    {"identifier": "Level_0", "pxWid": 48, "pxHei": 32,
+     "fieldInstances": [
+       {"__identifier": "dark", "__type": "Bool", "__value": true}],
+     "layerInstances": [
+       {"__identifier": "Entities", "__type": "Entities",
+        "__gridSize": 16, "entityInstances": [
+          {"__identifier": "Player", "px": [8, 24], "__pivot": [0.5, 1],
+           "width": 16, "height": 8, "fieldInstances": [
+             {"__identifier": "color", "__type": "Color",
+              "__value": "#00FF00"}]}
+        ]},
+       {"__identifier": "Walls", "__type": "Tiles", "__gridSize": 16,
+        "__tilesetDefUid": 7, "visible": false,
+        "gridTiles": [
+          {"px": [0, 0], "t": 3, "f": 1},
+          {"px": [32, 16], "t": 2, "f": 3}
+        ]},
+       {"__identifier": "Floor", "__type": "AutoLayer", "__gridSize": 16,
+        "__tilesetDefUid": 7,
+        "autoLayerTiles": [{"px": [16, 16], "t": 1, "f": 0}]}
+     ]}
+  ]
+}"##;
+        let map = MapData::from_ldtk(ldtk, "Level_0");
+
+        assert_eq!((map.width, map.height), (3, 2));
+        assert_eq!(map.properties["dark"], PropertyValue::Bool(true));
+        assert_eq!(
+            map.tilesets[0].tile_properties[&2]["data"],
+            PropertyValue::String("water".to_string())
+        );
+
+        // Layers go from the bottom one
+        let names: Vec<&str> =
+            map.tile_layers.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, ["Floor", "Walls"]);
+
+        let floor = &map.tile_layers[0];
+        assert_eq!(floor.tiles[1].unwrap().tile, Tile::new(1));
+
+        let walls = map.find_tile_layer("Walls").unwrap();
+        assert!(!walls.is_visible);
+        let top_left = walls.tiles[3].unwrap().tile;
+        assert_eq!(top_left.id, Some(3));
+        assert!(top_left.flip_x && !top_left.flip_y);
+        let bot_right = walls.tiles[2].unwrap().tile;
+        assert_eq!(bot_right.id, Some(2));
+        assert!(bot_right.flip_x && bot_right.flip_y);
+        assert_eq!(walls.tiles.iter().flatten().count(), 2);
+
+        let player =
+            &map.find_object_layer("Entities").unwrap().objects[0];
+        assert_eq!(player.name, "Player");
+        assert_rect_eq(
+            &player.shape,
+            point![0.0, 8.0],
+            point![16.0, 16.0],
+        );
+        assert_eq!(
+            player.properties["color"],
+            PropertyValue::Color(Color::new(0.0, 1.0, 0.0, 1.0))
+        );
+    }
+
+    #[test]
+    fn tilemap_layers_are_split_by_tileset() {
+        let data =
+            format!(r#"<data encoding="csv">{}</data>"#, get_tiled_csv());
+        let map = MapData::from_tmx(&get_tmx(&data), |_| get_props_tsx());
+        let textures = [
+            Texture::new(1, 32, 32, Some(TextureFormat::Rgba8)),
+            Texture::new(2, 36, 36, Some(TextureFormat::Rgba8)),
+        ];
+        let tilemap = map.create_tilemap(&textures);
+
+        let layers = tilemap.get_layers();
+        assert_eq!(layers.len(), 2);
+        assert!(layers.iter().all(|layer| layer.name == "ground"));
+        assert_eq!((layers[0].tileset_idx, layers[1].tileset_idx), (0, 1));
+
+        assert_eq!(tilemap.get_tile(0, 0, 0).id, Some(1));
+        assert_eq!(tilemap.get_tile(0, 2, 0).rotation, 1);
+        assert!(tilemap.get_tile(0, 2, 1).is_empty());
+        assert_eq!(tilemap.get_tile(1, 2, 1).id, Some(1));
+        assert!(tilemap.get_tile(1, 0, 1).is_empty());
+
+        assert_eq!(tilemap.get_tileset(1).margin, 1);
+        let animations = tilemap.get_tile_animations();
+        assert_eq!(animations.len(), 1);
+        assert_eq!(
+            (animations[0].tileset_idx, animations[0].tile_id),
+            (0, 1)
+        );
+    }
+}
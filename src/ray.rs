@@ -0,0 +1,369 @@
+use crate::bounding_volume::*;
+use crate::common::*;
+use crate::shapes::Triangle;
+use crate::vertex_buffer::VertexBufferCPU;
+use nalgebra::{Matrix4, Point2, Point3, Vector3};
+
+const BVH_MAX_LEAF_SIZE: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn new(origin: Point3<f32>, direction: Vector3<f32>) -> Self {
+        Self { origin, direction: direction.normalize() }
+    }
+
+    // Screen position is in window pixels with the top-left origin
+    pub fn from_screen(
+        camera: &Camera,
+        proj: &Projection,
+        window_size: (u32, u32),
+        screen_pos: Point2<f32>,
+    ) -> Self {
+        let near = camera.unproject(proj, window_size, screen_pos, -1.0);
+        let far = camera.unproject(proj, window_size, screen_pos, 1.0);
+
+        Self::new(near, far - near)
+    }
+
+    pub fn get_point(&self, t: f32) -> Point3<f32> {
+        self.origin + self.direction * t
+    }
+
+    pub fn transform(&self, mat: &Matrix4<f32>) -> Self {
+        Self::new(
+            mat.transform_point(&self.origin),
+            mat.transform_vector(&self.direction),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    pub t: f32,
+    pub point: Point3<f32>,
+    pub normal: Vector3<f32>,
+    pub triangle_idx: Option<usize>,
+}
+
+impl RayHit {
+    pub fn new(ray: &Ray, t: f32, normal: Vector3<f32>) -> Self {
+        Self {
+            t,
+            point: ray.get_point(t),
+            normal,
+            triangle_idx: None,
+        }
+    }
+
+    fn transform(
+        &self,
+        world_ray: &Ray,
+        model_mat: &Matrix4<f32>,
+        normal_mat: &Matrix4<f32>,
+    ) -> Self {
+        let point = model_mat.transform_point(&self.point);
+
+        Self {
+            t: (point - world_ray.origin).dot(&world_ray.direction),
+            point,
+            normal: normal_mat.transform_vector(&self.normal).normalize(),
+            triangle_idx: self.triangle_idx,
+        }
+    }
+}
+
+// Möller–Trumbore intersection. Both sides of the triangle are hit
+pub fn intersect_ray_with_triangle(
+    ray: &Ray,
+    triangle: &Triangle,
+) -> Option<RayHit> {
+    let ab = triangle.b - triangle.a;
+    let ac = triangle.c - triangle.a;
+    let p = ray.direction.cross(&ac);
+    let det = ab.dot(&p);
+    if det.abs() < f32::EPSILON {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    let s = ray.origin - triangle.a;
+    let u = s.dot(&p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = s.cross(&ab);
+    let v = ray.direction.dot(&q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = ac.dot(&q) * inv_det;
+    if t < 0.0 {
+        return None;
+    }
+
+    Some(RayHit::new(ray, t, ab.cross(&ac).normalize()))
+}
+
+// Slab intersection. Returns the entry hit or the exit one if the ray
+// starts inside the box
+pub fn intersect_ray_with_box(
+    ray: &Ray,
+    bbox: &BoundingBox,
+) -> Option<RayHit> {
+    let mut t_min = f32::NEG_INFINITY;
+    let mut t_max = f32::INFINITY;
+    let mut min_axis = 0;
+    let mut max_axis = 0;
+
+    for i in 0..3 {
+        let inv_dir = 1.0 / ray.direction[i];
+        let mut t0 = (bbox.min[i] - ray.origin[i]) * inv_dir;
+        let mut t1 = (bbox.max[i] - ray.origin[i]) * inv_dir;
+        if t0 > t1 {
+            std::mem::swap(&mut t0, &mut t1);
+        }
+
+        if t0 > t_min {
+            t_min = t0;
+            min_axis = i;
+        }
+        if t1 < t_max {
+            t_max = t1;
+            max_axis = i;
+        }
+        if t_min > t_max {
+            return None;
+        }
+    }
+
+    if t_max < 0.0 {
+        return None;
+    }
+
+    let (t, axis) = if t_min >= 0.0 {
+        (t_min, min_axis)
+    } else {
+        (t_max, max_axis)
+    };
+    let mut normal = Vector3::zeros();
+    normal[axis] = -ray.direction[axis].signum();
+
+    Some(RayHit::new(ray, t, normal))
+}
+
+pub fn intersect_ray_with_sphere(
+    ray: &Ray,
+    sphere: &BoundingSphere,
+) -> Option<RayHit> {
+    let oc = ray.origin - sphere.center;
+    let b = oc.dot(&ray.direction);
+    let c = oc.norm_squared() - sphere.radius * sphere.radius;
+    let det = b * b - c;
+    if det < 0.0 {
+        return None;
+    }
+
+    let det_sqrt = det.sqrt();
+    let t = if -b - det_sqrt >= 0.0 {
+        -b - det_sqrt
+    } else if -b + det_sqrt >= 0.0 {
+        -b + det_sqrt
+    } else {
+        return None;
+    };
+
+    let point = ray.get_point(t);
+    Some(RayHit::new(ray, t, (point - sphere.center).normalize()))
+}
+
+pub fn intersect_ray_with_vertex_buffer(
+    ray: &Ray,
+    vb: &VertexBufferCPU,
+    transform: Option<&Transformation>,
+) -> Option<RayHit> {
+    intersect_ray_in_model_space(ray, transform, |ray| {
+        get_closest_hit(vb.get_triangles().iter().enumerate().filter_map(
+            |(idx, triangle)| {
+                intersect_ray_with_triangle(ray, triangle).map(
+                    |mut hit| {
+                        hit.triangle_idx = Some(idx);
+                        hit
+                    },
+                )
+            },
+        ))
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BvhNode {
+    bbox: BoundingBox,
+    // Index of the left child for inner nodes (the right one goes next)
+    // or the first triangle for leaves
+    start: usize,
+    n_triangles: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    triangles: Vec<Triangle>,
+    triangle_ids: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn new(triangles: Vec<Triangle>) -> Self {
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(triangles.len() * 2),
+            triangle_ids: (0..triangles.len()).collect(),
+            triangles,
+        };
+
+        if !bvh.triangles.is_empty() {
+            bvh.nodes.push(bvh.create_node(0, bvh.triangles.len()));
+            bvh.split_node(0);
+        }
+
+        bvh
+    }
+
+    pub fn from_vertex_buffer(vb: &VertexBufferCPU) -> Self {
+        Self::new(vb.get_triangles())
+    }
+
+    pub fn get_bounding_box(&self) -> BoundingBox {
+        self.nodes.first().map_or(BoundingBox::zeros(), |n| n.bbox)
+    }
+
+    pub fn intersect(
+        &self,
+        ray: &Ray,
+        transform: Option<&Transformation>,
+    ) -> Option<RayHit> {
+        intersect_ray_in_model_space(ray, transform, |ray| {
+            self.intersect_local(ray)
+        })
+    }
+
+    fn intersect_local(&self, ray: &Ray) -> Option<RayHit> {
+        let mut closest: Option<RayHit> = None;
+        let mut stack = Vec::with_capacity(64);
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx];
+            let entry_t = if node.bbox.contains_point(&ray.origin) {
+                Some(0.0)
+            } else {
+                intersect_ray_with_box(ray, &node.bbox).map(|hit| hit.t)
+            };
+            match entry_t {
+                Some(t) if closest.is_none_or(|c| t <= c.t) => {}
+                _ => continue,
+            }
+
+            if node.n_triangles == 0 {
+                stack.push(node.start);
+                stack.push(node.start + 1);
+                continue;
+            }
+
+            for i in node.start..node.start + node.n_triangles {
+                let hit =
+                    intersect_ray_with_triangle(ray, &self.triangles[i]);
+                if let Some(mut hit) = hit {
+                    if closest.is_none_or(|c| hit.t < c.t) {
+                        hit.triangle_idx = Some(self.triangle_ids[i]);
+                        closest = Some(hit);
+                    }
+                }
+            }
+        }
+
+        closest
+    }
+
+    fn create_node(&self, start: usize, n_triangles: usize) -> BvhNode {
+        let points: Vec<Point3<f32>> = self.triangles
+            [start..start + n_triangles]
+            .iter()
+            .flat_map(|t| t.to_vertices())
+            .collect();
+
+        BvhNode {
+            bbox: BoundingBox::from_points(points.iter()),
+            start,
+            n_triangles,
+        }
+    }
+
+    fn split_node(&mut self, idx: usize) {
+        let BvhNode { bbox, start, n_triangles } = self.nodes[idx];
+        if n_triangles <= BVH_MAX_LEAF_SIZE {
+            return;
+        }
+
+        // Median split along the longest axis of the node
+        let size = bbox.get_size();
+        let axis = size.imax();
+        let get_centroid =
+            |t: &Triangle| (t.a[axis] + t.b[axis] + t.c[axis]) / 3.0;
+
+        let mut order: Vec<usize> = (start..start + n_triangles).collect();
+        order.sort_by(|&a, &b| {
+            get_centroid(&self.triangles[a])
+                .total_cmp(&get_centroid(&self.triangles[b]))
+        });
+        let triangles: Vec<Triangle> =
+            order.iter().map(|&i| self.triangles[i]).collect();
+        let triangle_ids: Vec<usize> =
+            order.iter().map(|&i| self.triangle_ids[i]).collect();
+        self.triangles[start..start + n_triangles]
+            .copy_from_slice(&triangles);
+        self.triangle_ids[start..start + n_triangles]
+            .copy_from_slice(&triangle_ids);
+
+        let n_left = n_triangles / 2;
+        let left = self.create_node(start, n_left);
+        let right = self.create_node(start + n_left, n_triangles - n_left);
+        let left_idx = self.nodes.len();
+        self.nodes.push(left);
+        self.nodes.push(right);
+        self.nodes[idx].start = left_idx;
+        self.nodes[idx].n_triangles = 0;
+
+        self.split_node(left_idx);
+        self.split_node(left_idx + 1);
+    }
+}
+
+fn intersect_ray_in_model_space(
+    ray: &Ray,
+    transform: Option<&Transformation>,
+    intersect: impl Fn(&Ray) -> Option<RayHit>,
+) -> Option<RayHit> {
+    let Some(transform) = transform else {
+        return intersect(ray);
+    };
+
+    let model_mat = transform.get_mat();
+    let inv_model_mat = model_mat.try_inverse()?;
+    let normal_mat = inv_model_mat.transpose();
+    let local_ray = ray.transform(&inv_model_mat);
+
+    intersect(&local_ray)
+        .map(|hit| hit.transform(ray, &model_mat, &normal_mat))
+}
+
+fn get_closest_hit(hits: impl Iterator<Item = RayHit>) -> Option<RayHit> {
+    hits.min_by(|a, b| a.t.total_cmp(&b.t))
+}
@@ -1,12 +1,23 @@
+use crate::bounding_volume::*;
 use crate::color::*;
 use crate::common::*;
 use crate::glyph_atlas::*;
+use crate::ray::Ray;
+use crate::scene::*;
 use crate::shapes::*;
+use crate::sprite::*;
+use crate::stroke::*;
+use crate::texture_atlas::*;
+use crate::tilemap::*;
 use crate::vertex_buffer::*;
+use core::f32::consts::PI;
 use core::fmt::Debug;
 use enum_iterator::{all, Sequence};
-use image::{load_from_memory_with_format, EncodableLayout, ImageFormat};
-use nalgebra::{point, Matrix4, Point2, Point3, Vector3};
+use image::{
+    load_from_memory_with_format, DynamicImage, EncodableLayout,
+    ImageFormat, RgbaImage,
+};
+use nalgebra::{point, vector, Matrix4, Point2, Point3, Vector2, Vector3};
 use std::num::NonZeroU32;
 
 use glow::HasContext;
@@ -15,7 +26,22 @@ const PRIMITIVE_VERT_SRC: &str = include_str!("../shaders/primitive.vert");
 const PRIMITIVE_FRAG_SRC: &str = include_str!("../shaders/primitive.frag");
 const SCREEN_RECT_VERT_SRC: &str =
     include_str!("../shaders/screen_rect.vert");
+const SKYBOX_VERT_SRC: &str = include_str!("../shaders/skybox.vert");
+const SKYBOX_FRAG_SRC: &str = include_str!("../shaders/skybox.frag");
 const MAX_N_VERTICES: usize = 1 << 15;
+// Max distance (in pixels) between the drawn polyline and the curve
+const CURVE_TOLERANCE: f32 = 0.25;
+const PRIMITIVE_ATTRIBS: [&str; 9] = [
+    "a_position",
+    "a_normal",
+    "a_texcoord",
+    "a_color",
+    "a_flags",
+    "a_joints",
+    "a_weights",
+    "a_sdf_params",
+    "a_sdf_radii",
+];
 
 #[derive(Copy, Clone, PartialEq)]
 struct VertexBufferGL {
@@ -25,46 +51,48 @@ struct VertexBufferGL {
     colors_vbo: glow::NativeBuffer,
     texcoords_vbo: glow::NativeBuffer,
     flags_vbo: glow::NativeBuffer,
+    joints_vbo: glow::NativeBuffer,
+    weights_vbo: glow::NativeBuffer,
+    sdf_params_vbo: glow::NativeBuffer,
+    sdf_radii_vbo: glow::NativeBuffer,
     indices_vbo: Option<glow::NativeBuffer>,
 
     n_vertices: usize,
     n_indices: usize,
+
+    bounding_box: BoundingBox,
+    bounding_sphere: BoundingSphere,
 }
 
 impl VertexBufferGL {
-    pub fn new(
-        gl: &glow::Context,
-        positions: &[f32],
-        normals: &[f32],
-        colors: &[f32],
-        texcoords: &[f32],
-        flags: &[u8],
-        indices: Option<&[u32]>,
-    ) -> Self {
-        let n_vertices = positions.len() / 3;
-        let mut n_indices = 0;
+    pub fn new_empty(gl: &glow::Context, n_vertices: usize) -> Self {
+        Self::new_from_cpu(gl, &VertexBufferCPU::new_zeros(n_vertices))
+    }
 
+    pub fn new_from_cpu(gl: &glow::Context, vb: &VertexBufferCPU) -> Self {
         let vao;
-        let positions_vbo;
-        let normals_vbo;
-        let texcoords_vbo;
-        let colors_vbo;
-        let flags_vbo;
         let mut indices_vbo = None;
         unsafe {
             vao = gl.create_vertex_array().unwrap();
             gl.bind_vertex_array(Some(vao));
         }
 
-        positions_vbo = create_attrib_vbo(gl, 0, 3, positions);
-        normals_vbo = create_attrib_vbo(gl, 1, 3, normals);
-        texcoords_vbo = create_attrib_vbo(gl, 2, 2, texcoords);
-        colors_vbo = create_attrib_vbo(gl, 3, 4, colors);
-        flags_vbo = create_attrib_vbo(gl, 4, 1, flags);
-
-        if let Some(indices) = indices {
+        let positions_vbo =
+            create_attrib_vbo(gl, 0, 3, vb.get_positions());
+        let normals_vbo = create_attrib_vbo(gl, 1, 3, vb.get_normals());
+        let texcoords_vbo =
+            create_attrib_vbo(gl, 2, 2, vb.get_texcoords());
+        let colors_vbo = create_attrib_vbo(gl, 3, 4, vb.get_colors());
+        let flags_vbo = create_attrib_vbo(gl, 4, 1, vb.get_flags());
+        let joints_vbo = create_attrib_vbo(gl, 5, 4, vb.get_joints());
+        let weights_vbo = create_attrib_vbo(gl, 6, 4, vb.get_weights());
+        let sdf_params_vbo =
+            create_attrib_vbo(gl, 7, 4, vb.get_sdf_params());
+        let sdf_radii_vbo =
+            create_attrib_vbo(gl, 8, 4, vb.get_sdf_radii());
+
+        if let Some(indices) = vb.get_indices() {
             indices_vbo = Some(create_indices_vbo(gl, indices));
-            n_indices = indices.len();
         }
 
         Self {
@@ -74,35 +102,18 @@ impl VertexBufferGL {
             colors_vbo,
             texcoords_vbo,
             flags_vbo,
+            joints_vbo,
+            weights_vbo,
+            sdf_params_vbo,
+            sdf_radii_vbo,
             indices_vbo,
 
-            n_vertices,
-            n_indices,
-        }
-    }
-
-    pub fn new_empty(gl: &glow::Context, n_vertices: usize) -> Self {
-        Self::new(
-            gl,
-            &vec![0.0; n_vertices * 3],
-            &vec![0.0; n_vertices * 3],
-            &vec![0.0; n_vertices * 4],
-            &vec![0.0; n_vertices * 2],
-            &vec![0; n_vertices],
-            None,
-        )
-    }
+            n_vertices: vb.get_n_vertcies(),
+            n_indices: vb.get_n_indices(),
 
-    pub fn new_from_cpu(gl: &glow::Context, vb: &VertexBufferCPU) -> Self {
-        Self::new(
-            gl,
-            vb.get_positions(),
-            vb.get_normals(),
-            vb.get_colors(),
-            vb.get_texcoords(),
-            vb.get_flags(),
-            vb.get_indices(),
-        )
+            bounding_box: vb.get_bounding_box(),
+            bounding_sphere: vb.get_bounding_sphere(),
+        }
     }
 
     pub fn bind(&self, gl: &glow::Context) {
@@ -112,108 +123,120 @@ impl VertexBufferGL {
         }
     }
 
-    fn set_from_cpu(&mut self, gl: &glow::Context, vb: &VertexBufferCPU) {
-        self.set_data(
-            gl,
-            vb.get_positions(),
-            vb.get_normals(),
-            vb.get_texcoords(),
-            vb.get_colors(),
-            vb.get_flags(),
-            vb.get_indices(),
-        );
+    fn delete(&self, gl: &glow::Context) {
+        let vbos = [
+            self.positions_vbo,
+            self.normals_vbo,
+            self.colors_vbo,
+            self.texcoords_vbo,
+            self.flags_vbo,
+            self.joints_vbo,
+            self.weights_vbo,
+            self.sdf_params_vbo,
+            self.sdf_radii_vbo,
+        ];
+
+        unsafe {
+            gl.delete_vertex_array(self.vao);
+            for vbo in vbos.into_iter().chain(self.indices_vbo) {
+                gl.delete_buffer(vbo);
+            }
+        }
     }
 
-    fn set_from_cpu_slice(
-        &mut self,
-        gl: &glow::Context,
-        vb: &VertexBufferCPU,
-        from_vertex: usize,
-        n_vertices: usize,
-    ) {
-        self.set_data(
-            gl,
-            vb.get_positions_slice(from_vertex, n_vertices),
-            vb.get_normals_slice(from_vertex, n_vertices),
-            vb.get_texcoords_slice(from_vertex, n_vertices),
-            vb.get_colors_slice(from_vertex, n_vertices),
-            vb.get_flags_slice(from_vertex, n_vertices),
-            None,
-        );
+    fn set_from_cpu(&mut self, gl: &glow::Context, vb: &VertexBufferCPU) {
+        self.set_from_cpu_slice(gl, vb, 0, vb.get_n_vertcies());
+
+        self.n_indices = vb.get_n_indices();
+        if let (Some(vbo), Some(data)) =
+            (self.indices_vbo, vb.get_indices())
+        {
+            update_vbo(gl, glow::ELEMENT_ARRAY_BUFFER, vbo, data);
+        } else if self.indices_vbo.is_none() && vb.get_indices().is_some()
+        {
+            panic!("Can't set indices for the unindexed vertex buffer");
+        } else if self.indices_vbo.is_some() && vb.get_indices().is_none()
+        {
+            panic!("Expecting indexes for the indexed vertex buffer");
+        }
     }
 
-    fn set_data(
+    // Reallocates the buffers, so the new data can have any size
+    fn reset_from_cpu(
         &mut self,
         gl: &glow::Context,
-        positions: &[f32],
-        normals: &[f32],
-        texcoords: &[f32],
-        colors: &[f32],
-        flags: &[u8],
-        indices: Option<&[u32]>,
+        vb: &VertexBufferCPU,
     ) {
-        self.n_vertices = positions.len() / 3;
-        if normals.len() / 3 != self.n_vertices
-            || texcoords.len() / 2 != self.n_vertices
-            || colors.len() / 4 != self.n_vertices
-            || flags.len() != self.n_vertices
-        {
-            panic!("Can't set vertex buffer data with inconsistent number of components in the arrays");
+        let vbos: [(glow::NativeBuffer, &[u8]); 9] = [
+            (self.positions_vbo, cast_slice_to_u8(vb.get_positions())),
+            (self.normals_vbo, cast_slice_to_u8(vb.get_normals())),
+            (self.texcoords_vbo, cast_slice_to_u8(vb.get_texcoords())),
+            (self.colors_vbo, cast_slice_to_u8(vb.get_colors())),
+            (self.flags_vbo, cast_slice_to_u8(vb.get_flags())),
+            (self.joints_vbo, cast_slice_to_u8(vb.get_joints())),
+            (self.weights_vbo, cast_slice_to_u8(vb.get_weights())),
+            (self.sdf_params_vbo, cast_slice_to_u8(vb.get_sdf_params())),
+            (self.sdf_radii_vbo, cast_slice_to_u8(vb.get_sdf_radii())),
+        ];
+        for (vbo, data) in vbos {
+            realloc_vbo(gl, glow::ARRAY_BUFFER, vbo, data);
         }
 
-        self.n_indices = indices.map_or(0, |data| data.len());
-
-        unsafe {
-            if let (Some(vbo), Some(data)) = (self.indices_vbo, indices) {
-                gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(vbo));
-                gl.buffer_sub_data_u8_slice(
-                    glow::ELEMENT_ARRAY_BUFFER,
-                    0,
-                    cast_slice_to_u8(data),
-                );
-            } else if self.indices_vbo.is_none() && indices.is_some() {
-                panic!(
-                    "Can't set indices for the unindexed vertex buffer"
-                );
-            } else if self.indices_vbo.is_some() && indices.is_none() {
-                panic!("Expecting indexes for the indexed vertex buffer");
+        match (self.indices_vbo, vb.get_indices()) {
+            (Some(vbo), Some(indices)) => {
+                let data = cast_slice_to_u8(indices);
+                realloc_vbo(gl, glow::ELEMENT_ARRAY_BUFFER, vbo, data);
             }
+            (None, Some(indices)) => {
+                unsafe { gl.bind_vertex_array(Some(self.vao)) };
+                self.indices_vbo = Some(create_indices_vbo(gl, indices));
+            }
+            (Some(vbo), None) => {
+                unsafe { gl.delete_buffer(vbo) };
+                self.indices_vbo = None;
+            }
+            (None, None) => {}
+        }
 
-            gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.positions_vbo));
-            gl.buffer_sub_data_u8_slice(
-                glow::ARRAY_BUFFER,
-                0,
-                cast_slice_to_u8(positions),
-            );
-
-            gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.normals_vbo));
-            gl.buffer_sub_data_u8_slice(
-                glow::ARRAY_BUFFER,
-                0,
-                cast_slice_to_u8(normals),
-            );
-
-            gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.texcoords_vbo));
-            gl.buffer_sub_data_u8_slice(
-                glow::ARRAY_BUFFER,
-                0,
-                cast_slice_to_u8(texcoords),
-            );
-
-            gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.colors_vbo));
-            gl.buffer_sub_data_u8_slice(
-                glow::ARRAY_BUFFER,
-                0,
-                cast_slice_to_u8(colors),
-            );
+        self.n_vertices = vb.get_n_vertcies();
+        self.n_indices = vb.get_n_indices();
+        self.bounding_box = vb.get_bounding_box();
+        self.bounding_sphere = vb.get_bounding_sphere();
+    }
 
-            gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.flags_vbo));
-            gl.buffer_sub_data_u8_slice(
-                glow::ARRAY_BUFFER,
-                0,
-                cast_slice_to_u8(flags),
-            );
-        }
+    fn set_from_cpu_slice(
+        &mut self,
+        gl: &glow::Context,
+        vb: &VertexBufferCPU,
+        from_vertex: usize,
+        n_vertices: usize,
+    ) {
+        self.n_vertices = n_vertices;
+
+        let positions = vb.get_positions_slice(from_vertex, n_vertices);
+        let normals = vb.get_normals_slice(from_vertex, n_vertices);
+        let texcoords = vb.get_texcoords_slice(from_vertex, n_vertices);
+        let colors = vb.get_colors_slice(from_vertex, n_vertices);
+        let flags = vb.get_flags_slice(from_vertex, n_vertices);
+        let joints = vb.get_joints_slice(from_vertex, n_vertices);
+        let weights = vb.get_weights_slice(from_vertex, n_vertices);
+        let sdf_params = vb.get_sdf_params_slice(from_vertex, n_vertices);
+        let sdf_radii = vb.get_sdf_radii_slice(from_vertex, n_vertices);
+
+        update_vbo(gl, glow::ARRAY_BUFFER, self.positions_vbo, positions);
+        update_vbo(gl, glow::ARRAY_BUFFER, self.normals_vbo, normals);
+        update_vbo(gl, glow::ARRAY_BUFFER, self.texcoords_vbo, texcoords);
+        update_vbo(gl, glow::ARRAY_BUFFER, self.colors_vbo, colors);
+        update_vbo(gl, glow::ARRAY_BUFFER, self.flags_vbo, flags);
+        update_vbo(gl, glow::ARRAY_BUFFER, self.joints_vbo, joints);
+        update_vbo(gl, glow::ARRAY_BUFFER, self.weights_vbo, weights);
+        update_vbo(
+            gl,
+            glow::ARRAY_BUFFER,
+            self.sdf_params_vbo,
+            sdf_params,
+        );
+        update_vbo(gl, glow::ARRAY_BUFFER, self.sdf_radii_vbo, sdf_radii);
     }
 }
 
@@ -257,6 +280,10 @@ impl Program {
                 shaders.push(shader);
             }
 
+            for (i, name) in PRIMITIVE_ATTRIBS.iter().enumerate() {
+                gl.bind_attrib_location(program, i as u32, name);
+            }
+
             gl.link_program(program);
             if !gl.get_program_link_status(program) {
                 panic!("{}", gl.get_program_info_log(program));
@@ -384,18 +411,23 @@ impl Texture {
     fn new_gl(
         gl: &glow::Context,
         data: Option<&[u8]>,
-        internal_format: u32,
         width: u32,
         height: u32,
-        format: u32,
-        data_type: u32,
-        filter: u32,
+        format: TextureFormat,
+        options: &TextureOptions,
     ) -> Self {
+        let (internal_format, gl_format, data_type) =
+            get_gl_texture_format(format);
+        let row_size = width as usize * format.get_pixel_size();
         let tex;
 
         unsafe {
             tex = gl.create_texture().unwrap();
             gl.bind_texture(glow::TEXTURE_2D, Some(tex));
+            gl.pixel_store_i32(
+                glow::UNPACK_ALIGNMENT,
+                get_unpack_alignment(row_size),
+            );
             gl.tex_image_2d(
                 glow::TEXTURE_2D,
                 0,
@@ -403,34 +435,167 @@ impl Texture {
                 width as i32,
                 height as i32,
                 0,
-                format,
+                gl_format,
                 data_type,
                 data,
             );
+        }
+        set_texture_options(gl, options, true);
+
+        Self::new(tex.0.get(), width, height, Some(format))
+    }
+
+    // Creates the texture with all mip levels given (from the largest
+    // one), so they are never generated
+    fn new_gl_with_levels(
+        gl: &glow::Context,
+        levels: &[&[u8]],
+        width: u32,
+        height: u32,
+        format: LevelFormat,
+        options: &TextureOptions,
+    ) -> Self {
+        let tex;
+
+        unsafe {
+            tex = gl.create_texture().unwrap();
+            gl.bind_texture(glow::TEXTURE_2D, Some(tex));
+
+            for (i, level) in levels.iter().enumerate() {
+                let level_width = (width >> i).max(1);
+                let level_height = (height >> i).max(1);
+                let level_size =
+                    format.get_level_size(level_width, level_height);
+                if level.len() < level_size {
+                    panic!(
+                        "Texture level {} should have {} bytes, got {}",
+                        i,
+                        level_size,
+                        level.len()
+                    );
+                }
+                let level = &level[..level_size];
+
+                match format {
+                    LevelFormat::Uncompressed(format) => {
+                        let (internal_format, gl_format, data_type) =
+                            get_gl_texture_format(format);
+                        let row_size =
+                            level_width as usize * format.get_pixel_size();
+                        gl.pixel_store_i32(
+                            glow::UNPACK_ALIGNMENT,
+                            get_unpack_alignment(row_size),
+                        );
+                        gl.tex_image_2d(
+                            glow::TEXTURE_2D,
+                            i as i32,
+                            internal_format as i32,
+                            level_width as i32,
+                            level_height as i32,
+                            0,
+                            gl_format,
+                            data_type,
+                            Some(level),
+                        );
+                    }
+                    LevelFormat::Compressed(format) => {
+                        gl.compressed_tex_image_2d(
+                            glow::TEXTURE_2D,
+                            i as i32,
+                            get_gl_compressed_texture_format(format)
+                                as i32,
+                            level_width as i32,
+                            level_height as i32,
+                            0,
+                            level_size as i32,
+                            level,
+                        );
+                    }
+                }
+            }
 
             gl.tex_parameter_i32(
                 glow::TEXTURE_2D,
-                glow::TEXTURE_WRAP_S,
-                glow::REPEAT as i32,
+                glow::TEXTURE_BASE_LEVEL,
+                0,
             );
             gl.tex_parameter_i32(
                 glow::TEXTURE_2D,
-                glow::TEXTURE_WRAP_T,
-                glow::REPEAT as i32,
+                glow::TEXTURE_MAX_LEVEL,
+                levels.len() as i32 - 1,
             );
+        }
+        set_texture_options(gl, options, false);
+
+        let format = match format {
+            LevelFormat::Uncompressed(format) => Some(format),
+            LevelFormat::Compressed(_) => None,
+        };
+
+        Self {
+            has_preloaded_levels: true,
+            ..Self::new(tex.0.get(), width, height, format)
+        }
+    }
+
+    fn to_glow(&self) -> glow::Texture {
+        glow::NativeTexture(NonZeroU32::new(self.idx).unwrap())
+    }
+
+    fn bind(&self, gl: &glow::Context) {
+        unsafe {
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.to_glow()));
+        }
+    }
+}
+
+impl Cubemap {
+    fn new_gl(gl: &glow::Context, faces: &[&[u8]; 6], size: u32) -> Self {
+        let tex;
+
+        unsafe {
+            tex = gl.create_texture().unwrap();
+            gl.bind_texture(glow::TEXTURE_CUBE_MAP, Some(tex));
+            gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 4);
+
+            for (i, face) in faces.iter().enumerate() {
+                gl.tex_image_2d(
+                    glow::TEXTURE_CUBE_MAP_POSITIVE_X + i as u32,
+                    0,
+                    glow::RGBA as i32,
+                    size as i32,
+                    size as i32,
+                    0,
+                    glow::RGBA,
+                    glow::UNSIGNED_BYTE,
+                    Some(face),
+                );
+            }
+
+            for wrap in [
+                glow::TEXTURE_WRAP_S,
+                glow::TEXTURE_WRAP_T,
+                glow::TEXTURE_WRAP_R,
+            ] {
+                gl.tex_parameter_i32(
+                    glow::TEXTURE_CUBE_MAP,
+                    wrap,
+                    glow::CLAMP_TO_EDGE as i32,
+                );
+            }
             gl.tex_parameter_i32(
-                glow::TEXTURE_2D,
+                glow::TEXTURE_CUBE_MAP,
                 glow::TEXTURE_MAG_FILTER,
-                filter as i32,
+                glow::LINEAR as i32,
             );
             gl.tex_parameter_i32(
-                glow::TEXTURE_2D,
+                glow::TEXTURE_CUBE_MAP,
                 glow::TEXTURE_MIN_FILTER,
-                filter as i32,
+                glow::LINEAR as i32,
             );
         }
 
-        Self::new(tex.0.get(), width, height)
+        Self::new(tex.0.get(), size)
     }
 
     fn to_glow(&self) -> glow::Texture {
@@ -439,23 +604,35 @@ impl Texture {
 
     fn bind(&self, gl: &glow::Context) {
         unsafe {
-            gl.bind_texture(glow::TEXTURE_2D, Some(self.to_glow()));
+            gl.bind_texture(glow::TEXTURE_CUBE_MAP, Some(self.to_glow()));
         }
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+struct Skybox {
+    cubemap: Cubemap,
+    inv_view_proj_mat: Matrix4<f32>,
+}
+
 #[derive(Debug, Clone, Default)]
 struct DrawCall {
     vb_idx: usize,
     from_vertex: usize,
     n_vertices: usize,
+    // Whole buffer is drawn. Its number of vertices is taken when the
+    // draw call is submitted, since the buffer can be updated after the
+    // draw call is queued
+    is_whole_vb: bool,
     tex: Option<Texture>,
-    transform: Option<Transformation>,
+    transform: Option<Matrix4<f32>>,
     camera: Option<Camera>,
     proj: Option<Projection>,
     material: Material,
     is_font: bool,
     depth_test: bool,
+    fog: Option<Fog>,
+    joints: Option<(usize, usize)>,
 }
 
 impl DrawCall {
@@ -464,7 +641,7 @@ impl DrawCall {
         from_vertex: usize,
         n_vertices: usize,
         tex: Option<Texture>,
-        transform: Option<Transformation>,
+        transform: Option<Matrix4<f32>>,
         camera: Option<Camera>,
         proj: Option<Projection>,
         material: Material,
@@ -475,6 +652,7 @@ impl DrawCall {
             vb_idx,
             from_vertex,
             n_vertices,
+            is_whole_vb: false,
             tex,
             transform,
             camera,
@@ -482,14 +660,31 @@ impl DrawCall {
             material,
             is_font,
             depth_test,
+            fog: None,
+            joints: None,
         }
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+enum FreedResource {
+    VertexBuffer(usize),
+    Texture(Texture),
+    Cubemap(Cubemap),
+    Program(u32),
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderStats {
+    pub n_draw_calls: usize,
+    pub n_culled_draw_calls: usize,
+}
+
 pub struct Renderer {
     window: sdl2::video::Window,
     gl: glow::Context,
     program: Program,
+    skybox_program: Program,
 
     ms_fbo: Option<glow::NativeFramebuffer>,
 
@@ -498,8 +693,29 @@ pub struct Renderer {
 
     vb_cpu: VertexBufferCPU,
     vertex_buffers: Vec<VertexBufferGL>,
+    vb_generations: Vec<u32>,
+    free_vb_slots: Vec<usize>,
+    // Deleted after the current frame is rendered, since the queued draw
+    // calls can still use them
+    freed_resources: Vec<FreedResource>,
     draw_calls: Vec<DrawCall>,
     lights: Vec<Light>,
+    // Last set ones, kept after `end_drawing` for the screen-world
+    // conversions in the update code
+    camera: Option<Camera>,
+    proj: Option<Projection>,
+    // Ones which are actually drawn with. Differ from the above only if
+    // the render-only effects (e.g. camera shake) are applied
+    render_camera: Option<Camera>,
+    render_proj: Option<Projection>,
+
+    joints_ubo: glow::NativeBuffer,
+    joint_mats: Vec<Matrix4<f32>>,
+
+    frustum_culling: bool,
+    stats: RenderStats,
+
+    skybox: Option<Skybox>,
 }
 
 impl Renderer {
@@ -561,6 +777,8 @@ impl Renderer {
         // ---------------------------------------------------------------
         let program =
             Program::new_gl(&gl, PRIMITIVE_VERT_SRC, PRIMITIVE_FRAG_SRC);
+        let skybox_program =
+            Program::new_gl(&gl, SKYBOX_VERT_SRC, SKYBOX_FRAG_SRC);
 
         let mut ms_fbo = None;
         let postfx_tex;
@@ -636,12 +854,13 @@ impl Renderer {
             postfx_tex = Texture::new_gl(
                 &gl,
                 None,
-                glow::RGBA32F,
                 window_size.0,
                 window_size.1,
-                glow::RGBA,
-                glow::FLOAT,
-                glow::NEAREST,
+                TextureFormat::Rgba32F,
+                &TextureOptions::new(
+                    TextureFilter::Nearest,
+                    TextureWrap::Repeat,
+                ),
             );
 
             gl.framebuffer_texture_2d(
@@ -661,10 +880,30 @@ impl Renderer {
         let vertex_buffers =
             vec![VertexBufferGL::new_empty(&gl, MAX_N_VERTICES)];
 
+        let joints_ubo;
+        unsafe {
+            joints_ubo = gl.create_buffer().unwrap();
+            gl.bind_buffer(glow::UNIFORM_BUFFER, Some(joints_ubo));
+            gl.buffer_data_size(
+                glow::UNIFORM_BUFFER,
+                (MAX_N_JOINTS * 16 * std::mem::size_of::<f32>()) as i32,
+                glow::DYNAMIC_DRAW,
+            );
+            gl.bind_buffer_base(glow::UNIFORM_BUFFER, 0, Some(joints_ubo));
+
+            let program = program.to_glow();
+            if let Some(idx) =
+                gl.get_uniform_block_index(program, "u_joints_block")
+            {
+                gl.uniform_block_binding(program, idx, 0);
+            }
+        }
+
         Self {
             window,
             gl,
             program,
+            skybox_program,
 
             ms_fbo,
 
@@ -673,8 +912,23 @@ impl Renderer {
 
             vb_cpu: VertexBufferCPU::new_empty(),
             vertex_buffers,
+            vb_generations: vec![0],
+            free_vb_slots: vec![],
+            freed_resources: vec![],
             draw_calls: Vec::with_capacity(128),
             lights: Vec::with_capacity(128),
+            camera: None,
+            proj: None,
+            render_camera: None,
+            render_proj: None,
+
+            joints_ubo,
+            joint_mats: Vec::with_capacity(MAX_N_JOINTS),
+
+            frustum_culling: true,
+            stats: RenderStats::default(),
+
+            skybox: None,
         }
     }
 
@@ -688,6 +942,14 @@ impl Renderer {
         w as f32 / h as f32
     }
 
+    pub fn get_stats(&self) -> RenderStats {
+        self.stats
+    }
+
+    pub fn set_frustum_culling(&mut self, is_set: bool) {
+        self.frustum_culling = is_set;
+    }
+
     pub fn load_program(
         &mut self,
         vert_src: &str,
@@ -700,107 +962,530 @@ impl Renderer {
         self.load_program(SCREEN_RECT_VERT_SRC, frag_src)
     }
 
+    // Pixel format is guessed from the number of bytes per pixel
     pub fn load_texture_from_pixel_bytes(
         &mut self,
         bytes: &[u8],
         width: u32,
         height: u32,
+        options: TextureOptions,
     ) -> Texture {
-        let n_components = bytes.len() as u32 / (width * height);
-        let (format, internal_format, alignment) = match n_components {
-            1 => {
-                #[cfg(target_os = "emscripten")]
-                {
-                    (glow::ALPHA, glow::ALPHA, 1)
-                }
+        let n_components = bytes.len() / (width * height) as usize;
+        let format = TextureFormat::from_n_components(n_components);
 
-                #[cfg(not(target_os = "emscripten"))]
-                {
-                    (glow::ALPHA, glow::RGBA, 1)
-                }
-            }
-            4 => (glow::RGBA, glow::RGBA, 4),
-            _ => {
-                panic!(
-                    "Can't load texture with {}-components pixel",
-                    n_components
-                )
-            }
-        };
+        self.load_texture_with_format(
+            bytes, width, height, format, options,
+        )
+    }
 
-        unsafe {
-            self.gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, alignment);
+    // Bytes are the tightly packed rows from the bottom one
+    pub fn load_texture_with_format(
+        &mut self,
+        bytes: &[u8],
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+        options: TextureOptions,
+    ) -> Texture {
+        let size = (width * height) as usize * format.get_pixel_size();
+        if bytes.len() != size {
+            panic!(
+                "{}x{} {:?} texture should have {} bytes, got {}",
+                width,
+                height,
+                format,
+                size,
+                bytes.len()
+            );
         }
 
         Texture::new_gl(
             &self.gl,
             Some(bytes),
-            internal_format,
             width,
             height,
             format,
-            glow::UNSIGNED_BYTE,
-            glow::LINEAR,
+            &options,
         )
     }
 
-    pub fn load_texture_from_image_bytes(
+    // Texture with the undefined content, which is expected to be filled
+    // with `update_texture`
+    pub fn create_texture(
         &mut self,
-        bytes: &[u8],
-        format: ImageFormat,
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+        options: TextureOptions,
     ) -> Texture {
-        let image = load_from_memory_with_format(bytes, format)
-            .expect("Can't decode image bytes")
-            .flipv()
-            .into_rgba8();
-
-        self.load_texture_from_pixel_bytes(
-            image.as_bytes(),
-            image.width(),
-            image.height(),
-        )
+        Texture::new_gl(&self.gl, None, width, height, format, &options)
     }
 
-    pub fn load_texture_from_glyph_atlas(
+    pub fn create_streaming_texture(
         &mut self,
-        atlas: &GlyphAtlas,
-    ) -> Texture {
-        self.load_texture_from_pixel_bytes(
-            &atlas.pixels,
-            atlas.image_width,
-            atlas.image_height,
-        )
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+        options: TextureOptions,
+    ) -> StreamingTexture {
+        StreamingTexture::new([
+            self.create_texture(width, height, format, options),
+            self.create_texture(width, height, format, options),
+        ])
     }
 
-    pub fn load_vertex_buffer_from_cpu(
+    // Region is given in the texture pixels with the bottom-left origin
+    // (as `Sprite::region`), bytes are its rows from the bottom one
+    pub fn update_texture(
         &mut self,
-        vb: &VertexBufferCPU,
-    ) -> usize {
-        let vb = VertexBufferGL::new_from_cpu(&self.gl, vb);
-        self.vertex_buffers.push(vb);
+        tex: Texture,
+        region: Rectangle,
+        bytes: &[u8],
+    ) {
+        let x = region.get_min_x().round() as i32;
+        let y = region.get_min_y().round() as i32;
+        let width = region.get_width().round() as i32;
+        let height = region.get_height().round() as i32;
+        if x < 0
+            || y < 0
+            || width <= 0
+            || height <= 0
+            || x + width > tex.width as i32
+            || y + height > tex.height as i32
+        {
+            panic!(
+                "Can't update region {:?} of the {}x{} texture",
+                region, tex.width, tex.height
+            );
+        }
 
-        self.vertex_buffers.len() - 1
+        let format = tex
+            .format
+            .expect("Can't update the block-compressed texture");
+        let n_pixels = (width * height) as usize;
+        if bytes.len() != n_pixels * format.get_pixel_size() {
+            panic!(
+                "Expecting {} {:?} pixels for the texture update, got {} \
+                 bytes",
+                n_pixels,
+                format,
+                bytes.len()
+            );
+        }
+
+        let row_size = width as usize * format.get_pixel_size();
+        let (_, format, data_type) = get_gl_texture_format(format);
+        unsafe {
+            self.gl.bind_texture(glow::TEXTURE_2D, Some(tex.to_glow()));
+            self.gl.pixel_store_i32(
+                glow::UNPACK_ALIGNMENT,
+                get_unpack_alignment(row_size),
+            );
+            self.gl.tex_sub_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                x,
+                y,
+                width,
+                height,
+                format,
+                data_type,
+                glow::PixelUnpackData::Slice(bytes),
+            );
+
+            let min_filter = self.gl.get_tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MIN_FILTER,
+            ) as u32;
+            if !tex.has_preloaded_levels
+                && min_filter != glow::NEAREST
+                && min_filter != glow::LINEAR
+            {
+                self.gl.generate_mipmap(glow::TEXTURE_2D);
+            }
+        }
     }
 
-    fn draw_vertex(
+    // Uploads the whole texture content into the back texture and makes
+    // it the front one
+    pub fn update_streaming_texture(
         &mut self,
-        position: Point3<f32>,
-        normal: Option<Vector3<f32>>,
-        color: Option<Color>,
-        texcoord: Option<Point2<f32>>,
+        tex: &mut StreamingTexture,
+        bytes: &[u8],
     ) {
-        let draw_call = self.get_default_draw_call();
-        draw_call.n_vertices += 1;
+        let back = tex.get_back_texture();
+        let size = Vector2::new(back.width as f32, back.height as f32);
+        let region = Rectangle::from_bot_left(Point2::origin(), size);
 
-        self.vb_cpu.push_vertex(position, normal, color, texcoord);
+        self.update_texture(back, region, bytes);
+        tex.swap();
     }
 
-    pub fn draw_triangle(
+    pub fn load_texture_from_image_bytes(
         &mut self,
-        triangle: Triangle,
-        normals: Option<Triangle>,
-        texcoords: Option<Triangle>,
-        color: Option<Color>,
+        bytes: &[u8],
+        format: ImageFormat,
+        options: TextureOptions,
+    ) -> Texture {
+        let image = load_from_memory_with_format(bytes, format)
+            .expect("Can't decode image bytes")
+            .flipv();
+
+        // Keep the float images (like HDR) in the full range
+        if let DynamicImage::ImageRgb32F(_)
+        | DynamicImage::ImageRgba32F(_) = image
+        {
+            let image = image.into_rgba32f();
+            return self.load_texture_with_format(
+                image.as_bytes(),
+                image.width(),
+                image.height(),
+                TextureFormat::Rgba32F,
+                options,
+            );
+        }
+
+        let image = image.into_rgba8();
+        self.load_texture_with_format(
+            image.as_bytes(),
+            image.width(),
+            image.height(),
+            TextureFormat::Rgba8,
+            options,
+        )
+    }
+
+    // KTX2 and DDS data is uploaded as is, so the first stored row (the
+    // top one for the most of the tools) ends up at v = 0. Flip such
+    // textures on export or flip the texcoords. Mip levels are taken from
+    // the file and never generated
+    pub fn load_texture_from_ktx2_bytes(
+        &mut self,
+        bytes: &[u8],
+        options: TextureOptions,
+    ) -> Texture {
+        let reader = ktx2::Reader::new(bytes)
+            .unwrap_or_else(|e| panic!("Can't read KTX2 texture: {}", e));
+        let header = reader.header();
+        if let Some(scheme) = header.supercompression_scheme {
+            panic!(
+                "Supercompressed KTX2 textures ({:?}) aren't supported",
+                scheme
+            );
+        }
+        if header.pixel_depth > 1 || header.layer_count > 1 {
+            panic!("Only 2D KTX2 textures are supported");
+        }
+        if header.face_count != 1 {
+            panic!("KTX2 cubemaps aren't supported");
+        }
+
+        let format = header
+            .format
+            .and_then(get_ktx2_level_format)
+            .unwrap_or_else(|| {
+                panic!(
+                    "KTX2 texture format {:?} isn't supported",
+                    header.format
+                )
+            });
+        let levels: Vec<&[u8]> =
+            reader.levels().map(|level| level.data).collect();
+
+        self.load_texture_with_levels(
+            &levels,
+            header.pixel_width,
+            header.pixel_height.max(1),
+            format,
+            options,
+        )
+    }
+
+    pub fn load_texture_from_dds_bytes(
+        &mut self,
+        bytes: &[u8],
+        options: TextureOptions,
+    ) -> Texture {
+        let dds = ddsfile::Dds::read(bytes)
+            .unwrap_or_else(|e| panic!("Can't read DDS texture: {}", e));
+        if dds.get_depth() > 1 || dds.get_num_array_layers() > 1 {
+            panic!("Only 2D DDS textures are supported");
+        }
+
+        let format = dds
+            .get_dxgi_format()
+            .and_then(get_dxgi_level_format)
+            .or_else(|| {
+                dds.get_d3d_format().and_then(get_d3d_level_format)
+            })
+            .unwrap_or_else(|| {
+                panic!(
+                    "DDS texture format {:?} isn't supported",
+                    dds.get_dxgi_format().map(|f| format!("{:?}", f)).or(
+                        dds.get_d3d_format().map(|f| format!("{:?}", f))
+                    )
+                )
+            });
+
+        let (width, height) = (dds.get_width(), dds.get_height());
+        let mut data = dds.data.as_slice();
+        let mut levels = vec![];
+        for i in 0..dds.get_num_mipmap_levels().max(1) {
+            let size = format
+                .get_level_size((width >> i).max(1), (height >> i).max(1));
+            if data.len() < size {
+                panic!("DDS texture has truncated mip level {}", i);
+            }
+            levels.push(&data[..size]);
+            data = &data[size..];
+        }
+
+        self.load_texture_with_levels(
+            &levels, width, height, format, options,
+        )
+    }
+
+    // Block-compressed formats depend on the GPU and the platform (S3TC
+    // and BPTC are common on desktop, ETC2 and ASTC on mobile)
+    pub fn is_compressed_format_supported(
+        &self,
+        format: CompressedTextureFormat,
+    ) -> bool {
+        match get_compressed_format_extension(format) {
+            Some(extension) => self
+                .gl
+                .supported_extensions()
+                .iter()
+                .any(|e| e.ends_with(extension)),
+            None => true,
+        }
+    }
+
+    fn load_texture_with_levels(
+        &mut self,
+        levels: &[&[u8]],
+        width: u32,
+        height: u32,
+        format: LevelFormat,
+        options: TextureOptions,
+    ) -> Texture {
+        if levels.is_empty() {
+            panic!("Texture should have at least one mip level");
+        }
+        if let LevelFormat::Compressed(format) = format {
+            if !self.is_compressed_format_supported(format) {
+                panic!(
+                    "Compressed texture format {:?} isn't supported",
+                    format
+                );
+            }
+        }
+
+        Texture::new_gl_with_levels(
+            &self.gl, levels, width, height, format, &options,
+        )
+    }
+
+    pub fn load_texture_from_glyph_atlas(
+        &mut self,
+        atlas: &GlyphAtlas,
+        options: TextureOptions,
+    ) -> Texture {
+        self.load_texture_from_pixel_bytes(
+            &atlas.pixels,
+            atlas.image_width,
+            atlas.image_height,
+            options,
+        )
+    }
+
+    pub fn load_texture_from_atlas(
+        &mut self,
+        atlas: &TextureAtlas,
+        options: TextureOptions,
+    ) -> Texture {
+        let image = image::imageops::flip_vertical(&atlas.image);
+
+        self.load_texture_from_pixel_bytes(
+            image.as_bytes(),
+            image.width(),
+            image.height(),
+            options,
+        )
+    }
+
+    // Changes the sampling of the already loaded texture
+    pub fn set_texture_options(
+        &mut self,
+        tex: Texture,
+        options: TextureOptions,
+    ) {
+        unsafe {
+            self.gl.bind_texture(glow::TEXTURE_2D, Some(tex.to_glow()));
+        }
+        set_texture_options(&self.gl, &options, !tex.has_preloaded_levels);
+    }
+
+    pub fn load_cubemap_from_image_bytes(
+        &mut self,
+        faces: [&[u8]; 6],
+        format: ImageFormat,
+    ) -> Cubemap {
+        let images = faces.map(|bytes| {
+            load_from_memory_with_format(bytes, format)
+                .expect("Can't decode image bytes")
+                .into_rgba8()
+        });
+
+        let size = images[0].width();
+        if images
+            .iter()
+            .any(|i| i.width() != size || i.height() != size)
+        {
+            panic!(
+                "Cubemap faces should be square images of the same size"
+            );
+        }
+
+        let faces = [0, 1, 2, 3, 4, 5].map(|i| images[i].as_bytes());
+        Cubemap::new_gl(&self.gl, &faces, size)
+    }
+
+    pub fn load_cubemap_from_equirect_image_bytes(
+        &mut self,
+        bytes: &[u8],
+        format: ImageFormat,
+        size: u32,
+    ) -> Cubemap {
+        let image = load_from_memory_with_format(bytes, format)
+            .expect("Can't decode image bytes")
+            .into_rgba8();
+        let faces = equirect_to_cubemap_faces(&image, size);
+
+        let faces = [0, 1, 2, 3, 4, 5].map(|i| faces[i].as_slice());
+        Cubemap::new_gl(&self.gl, &faces, size)
+    }
+
+    pub fn load_vertex_buffer_from_cpu(
+        &mut self,
+        vb: &VertexBufferCPU,
+    ) -> VertexBufferIdx {
+        let vb = VertexBufferGL::new_from_cpu(&self.gl, vb);
+
+        let slot = if let Some(slot) = self.free_vb_slots.pop() {
+            self.vertex_buffers[slot] = vb;
+            slot
+        } else {
+            self.vertex_buffers.push(vb);
+            self.vb_generations.push(0);
+            self.vertex_buffers.len() - 1
+        };
+
+        VertexBufferIdx::new(slot, self.vb_generations[slot])
+    }
+
+    // Replaces the uploaded vertex buffer data keeping its index valid.
+    // Applied right away, so the draw calls of this frame which are
+    // already queued are rendered with the new data too
+    pub fn update_vertex_buffer(
+        &mut self,
+        vb_idx: VertexBufferIdx,
+        vb: &VertexBufferCPU,
+    ) {
+        let slot = self.get_vb_slot(vb_idx);
+        self.vertex_buffers[slot].reset_from_cpu(&self.gl, vb);
+    }
+
+    // The index becomes invalid right away, the GPU buffers are deleted
+    // after the current frame is rendered
+    pub fn free_vertex_buffer(&mut self, vb_idx: VertexBufferIdx) {
+        let slot = self.get_vb_slot(vb_idx);
+        self.vb_generations[slot] += 1;
+        self.freed_resources.push(FreedResource::VertexBuffer(slot));
+    }
+
+    // Textures, cubemaps and programs shouldn't be used after they are
+    // freed: unlike the vertex buffer indices they aren't checked
+    pub fn free_texture(&mut self, tex: Texture) {
+        self.freed_resources.push(FreedResource::Texture(tex));
+    }
+
+    pub fn free_streaming_texture(&mut self, tex: StreamingTexture) {
+        self.free_texture(tex.get_texture());
+        self.free_texture(tex.get_back_texture());
+    }
+
+    pub fn free_cubemap(&mut self, cubemap: Cubemap) {
+        self.freed_resources.push(FreedResource::Cubemap(cubemap));
+    }
+
+    pub fn free_program(&mut self, program: Program) {
+        self.freed_resources
+            .push(FreedResource::Program(program.idx));
+    }
+
+    // Frees the cached chunk buffers, they are rebuilt if the tilemap is
+    // drawn again
+    pub fn free_tilemap(&mut self, tilemap: &mut Tilemap) {
+        for vb_idx in tilemap.take_chunk_vb_idxs() {
+            self.free_vertex_buffer(vb_idx);
+        }
+    }
+
+    fn get_vb_slot(&self, vb_idx: VertexBufferIdx) -> usize {
+        if vb_idx.idx == 0 {
+            panic!("Can't use the immediate vertex buffer by index");
+        }
+
+        match self.vb_generations.get(vb_idx.idx) {
+            Some(&generation) if generation == vb_idx.generation => {
+                vb_idx.idx
+            }
+            Some(_) => panic!("Vertex buffer {:?} is freed", vb_idx),
+            None => panic!("Vertex buffer {:?} doesn't exist", vb_idx),
+        }
+    }
+
+    fn delete_freed_resources(&mut self) {
+        for resource in std::mem::take(&mut self.freed_resources) {
+            match resource {
+                FreedResource::VertexBuffer(slot) => {
+                    self.vertex_buffers[slot].delete(&self.gl);
+                    self.free_vb_slots.push(slot);
+                }
+                FreedResource::Texture(tex) => unsafe {
+                    self.gl.delete_texture(tex.to_glow());
+                },
+                FreedResource::Cubemap(cubemap) => unsafe {
+                    self.gl.delete_texture(cubemap.to_glow());
+                },
+                FreedResource::Program(idx) => unsafe {
+                    let program = NonZeroU32::new(idx).unwrap();
+                    self.gl.delete_program(glow::NativeProgram(program));
+                },
+            }
+        }
+    }
+
+    fn draw_vertex(
+        &mut self,
+        position: Point3<f32>,
+        normal: Option<Vector3<f32>>,
+        color: Option<Color>,
+        texcoord: Option<Point2<f32>>,
+    ) {
+        let draw_call = self.get_default_draw_call();
+        draw_call.n_vertices += 1;
+
+        self.vb_cpu.push_vertex(position, normal, color, texcoord);
+    }
+
+    pub fn draw_triangle(
+        &mut self,
+        triangle: Triangle,
+        normals: Option<Triangle>,
+        texcoords: Option<Triangle>,
+        color: Option<Color>,
     ) {
         let normals = if let Some(normals) = normals {
             [
@@ -850,15 +1535,439 @@ impl Renderer {
         texcoords: Option<Circle>,
         color: Option<Color>,
     ) {
-        let positions = circle.to_triangles();
-        let texcoords = if let Some(texcoords) = texcoords {
-            texcoords.to_triangles().map(|t| Some(t))
-        } else {
-            [None; CIRCLE_N_TRIANGLES]
+        let n_segments =
+            circle.get_n_segments(self.get_pixel_scale(circle.center));
+        let positions = circle.to_triangles(n_segments);
+        let texcoords = if let Some(texcoords) = texcoords {
+            texcoords
+                .to_triangles(n_segments)
+                .into_iter()
+                .map(Some)
+                .collect()
+        } else {
+            vec![None; positions.len()]
+        };
+
+        for (position, texcoord) in positions.into_iter().zip(texcoords) {
+            self.draw_triangle(position, None, texcoord, color);
+        }
+    }
+
+    pub fn draw_circle_outline(
+        &mut self,
+        circle: Circle,
+        style: &StrokeStyle,
+        color: Option<Color>,
+    ) {
+        let n_segments =
+            circle.get_n_segments(self.get_pixel_scale(circle.center));
+
+        self.draw_polyline(
+            &circle.get_points(n_segments),
+            true,
+            style,
+            color,
+        );
+    }
+
+    pub fn draw_ellipse(
+        &mut self,
+        ellipse: Ellipse,
+        color: Option<Color>,
+    ) {
+        let n_segments =
+            ellipse.get_n_segments(self.get_pixel_scale(ellipse.center));
+
+        self.draw_triangles(ellipse.to_triangles(n_segments), color);
+    }
+
+    pub fn draw_ellipse_outline(
+        &mut self,
+        ellipse: Ellipse,
+        style: &StrokeStyle,
+        color: Option<Color>,
+    ) {
+        let n_segments =
+            ellipse.get_n_segments(self.get_pixel_scale(ellipse.center));
+        let points = ellipse.get_points(n_segments);
+
+        self.draw_polyline(&points, true, style, color);
+    }
+
+    pub fn draw_arc(
+        &mut self,
+        arc: Arc,
+        style: &StrokeStyle,
+        color: Option<Color>,
+    ) {
+        let n_segments =
+            arc.get_n_segments(self.get_pixel_scale(arc.center));
+
+        self.draw_polyline(
+            &arc.get_points(n_segments),
+            false,
+            style,
+            color,
+        );
+    }
+
+    pub fn draw_sector(&mut self, sector: Sector, color: Option<Color>) {
+        let n_segments =
+            sector.get_n_segments(self.get_pixel_scale(sector.center));
+
+        self.draw_triangles(sector.to_triangles(n_segments), color);
+    }
+
+    pub fn draw_sector_outline(
+        &mut self,
+        sector: Sector,
+        style: &StrokeStyle,
+        color: Option<Color>,
+    ) {
+        let n_segments =
+            sector.get_n_segments(self.get_pixel_scale(sector.center));
+        let points = sector.get_points(n_segments);
+
+        self.draw_polyline(&points, true, style, color);
+    }
+
+    pub fn draw_ring(&mut self, ring: Ring, color: Option<Color>) {
+        let n_segments =
+            ring.get_n_segments(self.get_pixel_scale(ring.center));
+
+        self.draw_triangles(ring.to_triangles(n_segments), color);
+    }
+
+    pub fn draw_ring_outline(
+        &mut self,
+        ring: Ring,
+        style: &StrokeStyle,
+        color: Option<Color>,
+    ) {
+        let n_segments =
+            ring.get_n_segments(self.get_pixel_scale(ring.center));
+
+        for contour in ring.get_contours(n_segments) {
+            self.draw_polyline(&contour, true, style, color);
+        }
+    }
+
+    pub fn draw_rounded_rect(
+        &mut self,
+        rect: RoundedRectangle,
+        color: Option<Color>,
+    ) {
+        let center = rect.rect.get_center();
+        let n_segments = rect.get_n_segments(self.get_pixel_scale(center));
+
+        self.draw_triangles(rect.to_triangles(n_segments), color);
+    }
+
+    pub fn draw_rounded_rect_outline(
+        &mut self,
+        rect: RoundedRectangle,
+        style: &StrokeStyle,
+        color: Option<Color>,
+    ) {
+        let center = rect.rect.get_center();
+        let n_segments = rect.get_n_segments(self.get_pixel_scale(center));
+
+        self.draw_polyline(
+            &rect.get_points(n_segments),
+            true,
+            style,
+            color,
+        );
+    }
+
+    pub fn draw_sdf_circle(
+        &mut self,
+        circle: Circle,
+        color: Option<Color>,
+    ) {
+        self.draw_sdf_circle_outline(circle, 0.0, color);
+    }
+
+    pub fn draw_sdf_circle_outline(
+        &mut self,
+        circle: Circle,
+        thickness: f32,
+        color: Option<Color>,
+    ) {
+        let r = circle.radius;
+        let params = SdfParams::new(vector![r, r], [r; 4], thickness);
+
+        self.draw_sdf_quad(
+            circle.center,
+            vector![1.0, 0.0],
+            &params,
+            color,
+        );
+    }
+
+    // Partial rings have no SDF path and fall back to the triangles
+    pub fn draw_sdf_ring(&mut self, ring: Ring, color: Option<Color>) {
+        if !ring.is_full() {
+            self.draw_ring(ring, color);
+            return;
+        }
+
+        let circle = Circle::new(ring.center, ring.outer_radius);
+        let thickness = ring.outer_radius - ring.inner_radius;
+
+        self.draw_sdf_circle_outline(circle, thickness, color);
+    }
+
+    pub fn draw_sdf_rounded_rect(
+        &mut self,
+        rect: RoundedRectangle,
+        color: Option<Color>,
+    ) {
+        self.draw_sdf_rounded_rect_outline(rect, 0.0, color);
+    }
+
+    pub fn draw_sdf_rounded_rect_outline(
+        &mut self,
+        rect: RoundedRectangle,
+        thickness: f32,
+        color: Option<Color>,
+    ) {
+        let half_size = rect.rect.get_size() * 0.5;
+        let params =
+            SdfParams::new(half_size, rect.get_clamped_radii(), thickness);
+
+        self.draw_sdf_quad(
+            rect.rect.get_center(),
+            vector![1.0, 0.0],
+            &params,
+            color,
+        );
+    }
+
+    // Capsule for the round cap or a rotated box otherwise
+    pub fn draw_sdf_line(
+        &mut self,
+        line: Line,
+        thickness: f32,
+        cap: LineCap,
+        color: Option<Color>,
+    ) {
+        let d = line.e - line.s;
+        let Some(dir) = d.try_normalize(f32::EPSILON) else {
+            return;
+        };
+
+        let hw = 0.5 * thickness;
+        let half_len = 0.5 * d.norm();
+        let (half_size, radius) = match cap {
+            LineCap::Butt => (vector![half_len, hw], 0.0),
+            LineCap::Square => (vector![half_len + hw, hw], 0.0),
+            LineCap::Round => (vector![half_len + hw, hw], hw),
+        };
+        let params = SdfParams::new(half_size, [radius; 4], 0.0);
+        let center = nalgebra::center(&line.s, &line.e);
+
+        self.draw_sdf_quad(center, dir, &params, color);
+    }
+
+    // Quad around the shape, rotated so its x axis goes along the given
+    // direction. It's expanded by a pixel to fit the anti-aliased edge
+    fn draw_sdf_quad(
+        &mut self,
+        center: Point2<f32>,
+        x_axis: Vector2<f32>,
+        params: &SdfParams,
+        color: Option<Color>,
+    ) {
+        let margin = 1.0 / self.get_pixel_scale(center);
+        let half_size = params.half_size.add_scalar(margin);
+        let y_axis = vector![-x_axis.y, x_axis.x];
+
+        let corners = [
+            point![-half_size.x, -half_size.y],
+            point![half_size.x, -half_size.y],
+            point![half_size.x, half_size.y],
+            point![-half_size.x, half_size.y],
+        ];
+        for i in [0, 1, 2, 0, 2, 3] {
+            let local = corners[i];
+            let p = center + x_axis * local.x + y_axis * local.y;
+
+            let draw_call = self.get_default_draw_call();
+            draw_call.n_vertices += 1;
+            self.vb_cpu.push_sdf_vertex(
+                point![p.x, p.y, 0.0],
+                color,
+                local,
+                params,
+            );
+        }
+    }
+
+    fn draw_triangles(
+        &mut self,
+        triangles: Vec<Triangle>,
+        color: Option<Color>,
+    ) {
+        for triangle in triangles {
+            self.draw_triangle(triangle, None, None, color);
+        }
+    }
+
+    pub fn draw_line(
+        &mut self,
+        line: Line,
+        style: &StrokeStyle,
+        color: Option<Color>,
+    ) {
+        self.draw_triangles(stroke_line(&line, style), color);
+    }
+
+    pub fn draw_polyline(
+        &mut self,
+        points: &[Point2<f32>],
+        is_closed: bool,
+        style: &StrokeStyle,
+        color: Option<Color>,
+    ) {
+        let triangles = stroke_polyline(points, is_closed, style);
+
+        self.draw_triangles(triangles, color);
+    }
+
+    pub fn draw_curve(
+        &mut self,
+        curve: &Curve,
+        style: &StrokeStyle,
+        color: Option<Color>,
+    ) {
+        let rect = curve.get_bounding_rect();
+        let scale = self.get_pixel_scale(rect.get_center());
+        let points = curve.flatten(CURVE_TOLERANCE / scale);
+        let is_closed = matches!(
+            curve,
+            Curve::CatmullRom(CatmullRom { is_closed: true, .. })
+        );
+
+        self.draw_polyline(&points, is_closed, style, color);
+    }
+
+    pub fn draw_polygon(
+        &mut self,
+        polygon: &Polygon,
+        color: Option<Color>,
+    ) {
+        self.draw_triangles(polygon.get_triangles(), color);
+    }
+
+    pub fn draw_polygon_outline(
+        &mut self,
+        polygon: &Polygon,
+        style: &StrokeStyle,
+        color: Option<Color>,
+    ) {
+        for contour in polygon.get_contours() {
+            self.draw_polyline(contour, true, style, color);
+        }
+    }
+
+    pub fn draw_sprite(&mut self, sprite: &Sprite) {
+        self.set_tex(sprite.tex, false);
+
+        let positions = sprite.get_triangles();
+        let texcoords = sprite.get_texcoord_triangles();
+        for i in 0..2 {
+            self.draw_triangle(
+                positions[i],
+                None,
+                Some(texcoords[i]),
+                Some(sprite.tint),
+            );
+        }
+    }
+
+    pub fn draw_nine_slice(
+        &mut self,
+        nine_slice: &NineSlice,
+        rect: Rectangle,
+    ) {
+        self.set_tex(nine_slice.tex, false);
+
+        for (position, texcoords) in nine_slice.get_patches(rect) {
+            self.draw_rect(
+                position,
+                Some(texcoords),
+                Some(nine_slice.tint),
+            );
+        }
+    }
+
+    // Uploads the changed tilemap chunks and draws the ones visible by
+    // the current 2D camera (all chunks for the 3D one)
+    pub fn draw_tilemap(&mut self, tilemap: &mut Tilemap) {
+        let view_rect = self.get_view_rect();
+        let (cols, rows) = match view_rect {
+            Some(rect) => tilemap.get_visible_chunks(rect),
+            None => {
+                let (n_cols, n_rows) = tilemap.get_n_chunks();
+                (0..n_cols, 0..n_rows)
+            }
+        };
+
+        for layer_idx in 0..tilemap.get_layers().len() {
+            let layer = tilemap.get_layer(layer_idx);
+            if !layer.is_visible {
+                continue;
+            }
+
+            self.set_tex(
+                tilemap.get_tileset(layer.tileset_idx).tex,
+                false,
+            );
+            for row in rows.clone() {
+                for col in cols.clone() {
+                    self.update_tilemap_chunk(
+                        tilemap, layer_idx, col, row,
+                    );
+                    if tilemap.is_chunk_empty(layer_idx, col, row) {
+                        continue;
+                    }
+
+                    if let Some(vb_idx) =
+                        tilemap.get_chunk_vb_idx(layer_idx, col, row)
+                    {
+                        self.draw_vertex_buffer(
+                            vb_idx,
+                            None,
+                            Material::VertexColor,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn update_tilemap_chunk(
+        &mut self,
+        tilemap: &mut Tilemap,
+        layer_idx: usize,
+        col: u32,
+        row: u32,
+    ) {
+        let Some(vb) = tilemap.rebuild_chunk(layer_idx, col, row) else {
+            return;
         };
 
-        for i in 0..CIRCLE_N_TRIANGLES {
-            self.draw_triangle(positions[i], None, texcoords[i], color);
+        if vb.get_n_vertcies() == 0 {
+            return;
+        }
+
+        match tilemap.get_chunk_vb_idx(layer_idx, col, row) {
+            Some(vb_idx) => self.update_vertex_buffer(vb_idx, &vb),
+            None => {
+                let vb_idx = self.load_vertex_buffer_from_cpu(&vb);
+                tilemap.set_chunk_vb_idx(layer_idx, col, row, vb_idx);
+            }
         }
     }
 
@@ -868,41 +1977,234 @@ impl Renderer {
 
     pub fn draw_vertex_buffer(
         &mut self,
-        vb_idx: usize,
+        vb_idx: VertexBufferIdx,
         transform: Option<Transformation>,
         material: Material,
     ) {
-        let vb = self.vertex_buffers[vb_idx];
-        let mut draw_call = self.get_new_draw_call();
-        draw_call.vb_idx = vb_idx;
+        self.draw_vertex_buffer_with_mat(
+            vb_idx,
+            transform.map(|t| t.get_mat()),
+            material,
+        );
+    }
+
+    fn draw_vertex_buffer_with_mat(
+        &mut self,
+        vb_idx: VertexBufferIdx,
+        model_mat: Option<Matrix4<f32>>,
+        material: Material,
+    ) {
+        let slot = self.get_vb_slot(vb_idx);
+        let draw_call = self.get_new_draw_call();
+        draw_call.vb_idx = slot;
         draw_call.from_vertex = 0;
-        draw_call.n_vertices = vb.n_vertices;
-        draw_call.transform = transform;
+        draw_call.is_whole_vb = true;
+        draw_call.transform = model_mat;
         draw_call.material = material;
     }
 
+    pub fn draw_skinned_vertex_buffer(
+        &mut self,
+        vb_idx: VertexBufferIdx,
+        transform: Option<Transformation>,
+        material: Material,
+        joint_mats: &[Matrix4<f32>],
+    ) {
+        if joint_mats.len() > MAX_N_JOINTS {
+            panic!(
+                "Can't draw skinned vertex buffer with more than {} joints",
+                MAX_N_JOINTS
+            );
+        }
+
+        let from_joint = self.joint_mats.len();
+        self.joint_mats.extend_from_slice(joint_mats);
+        self.draw_vertex_buffer(vb_idx, transform, material);
+        self.get_curr_draw_call().joints =
+            Some((from_joint, joint_mats.len()));
+    }
+
     pub fn draw_vertex_buffer_slice(
         &mut self,
-        vb_idx: usize,
+        vb_idx: VertexBufferIdx,
         transform: Option<Transformation>,
         material: Material,
         from_vertex: usize,
         n_vertices: usize,
     ) {
+        let slot = self.get_vb_slot(vb_idx);
         let mut draw_call = self.get_new_draw_call();
-        draw_call.vb_idx = vb_idx;
+        draw_call.vb_idx = slot;
         draw_call.from_vertex = from_vertex;
         draw_call.n_vertices = n_vertices;
-        draw_call.transform = transform;
+        draw_call.transform = transform.map(|t| t.get_mat());
         draw_call.material = material;
     }
 
+    pub fn draw_scene(&mut self, scene: &Scene) {
+        for (idx, world_mat) in scene.get_world_mats() {
+            let node = scene.get_node(idx);
+
+            if let Some(mut light) = node.light {
+                light.position = if light.is_dir {
+                    Point3::from(
+                        world_mat.transform_vector(&light.position.coords),
+                    )
+                } else {
+                    world_mat.transform_point(&light.position)
+                };
+                self.set_light(light);
+            }
+
+            if let Some(vb_idx) = node.mesh {
+                self.draw_vertex_buffer_with_mat(
+                    vb_idx,
+                    Some(world_mat),
+                    node.material,
+                );
+            }
+        }
+    }
+
+    pub fn draw_skybox(&mut self, cubemap: Cubemap) {
+        let draw_call = self.get_curr_draw_call();
+        let view_mat = if let Some(camera) = draw_call.camera.as_ref() {
+            camera.get_mat()
+        } else {
+            panic!("Can't draw skybox without camera. Call `renderer.set_camera` before drawing");
+        };
+        let proj_mat = if let Some(proj) = draw_call.proj.as_ref() {
+            proj.get_mat()
+        } else {
+            panic!("Can't draw skybox without projection. Call `renderer.set_proj` before drawing");
+        };
+
+        let mut rotation_mat = view_mat;
+        rotation_mat.fixed_view_mut::<3, 1>(0, 3).fill(0.0);
+        let inv_view_proj_mat = (proj_mat * rotation_mat)
+            .try_inverse()
+            .expect("Skybox view-projection matrix should be invertible");
+
+        self.skybox = Some(Skybox { cubemap, inv_view_proj_mat });
+    }
+
     pub fn set_proj(&mut self, proj: Projection) {
+        self.set_render_proj(proj, proj);
+    }
+
+    // Draws with the `render_proj`, but keeps the `proj` for the
+    // screen-world conversions, so the render-only effects don't leak
+    // into them
+    pub fn set_render_proj(
+        &mut self,
+        proj: Projection,
+        render_proj: Projection,
+    ) {
+        self.proj = Some(proj);
+        self.render_proj = Some(render_proj);
         let curr_proj = self.get_curr_draw_call().proj;
-        if curr_proj.is_none() || curr_proj.is_some_and(|p| p != proj) {
+        if curr_proj.is_none()
+            || curr_proj.is_some_and(|p| p != render_proj)
+        {
             let draw_call = self.get_new_draw_call();
-            draw_call.proj = Some(proj);
+            draw_call.proj = Some(render_proj);
+        }
+    }
+
+    pub fn get_proj(&self) -> Option<Projection> {
+        self.proj
+    }
+
+    pub fn get_camera(&self) -> Option<Camera> {
+        self.camera
+    }
+
+    pub fn screen_to_world(&self, screen_pos: Point2<f32>) -> Point2<f32> {
+        let (camera, proj) = self.get_camera_and_proj();
+
+        camera.screen_to_world(&proj, self.get_window_size(), screen_pos)
+    }
+
+    pub fn world_to_screen(&self, world_pos: Point2<f32>) -> Point2<f32> {
+        let (camera, proj) = self.get_camera_and_proj();
+
+        camera.world_to_screen(&proj, self.get_window_size(), world_pos)
+    }
+
+    pub fn get_screen_ray(&self, screen_pos: Point2<f32>) -> Ray {
+        let (camera, proj) = self.get_camera_and_proj();
+
+        Ray::from_screen(
+            &camera,
+            &proj,
+            self.get_window_size(),
+            screen_pos,
+        )
+    }
+
+    // Number of window pixels per world unit around the given point. Used
+    // to pick the tessellation level of the curved shapes
+    fn get_pixel_scale(&self, world_pos: Point2<f32>) -> f32 {
+        let (Some(camera), Some(proj)) =
+            (self.render_camera, self.render_proj)
+        else {
+            return 1.0;
+        };
+
+        let window_size = self.get_window_size();
+        let origin = camera.world_to_screen(&proj, window_size, world_pos);
+        let scale = [vector![1.0, 0.0], vector![0.0, 1.0]]
+            .iter()
+            .map(|d| {
+                let p = world_pos + d;
+                (camera.world_to_screen(&proj, window_size, p) - origin)
+                    .norm()
+            })
+            .fold(0.0, f32::max);
+
+        if scale.is_finite() && scale > 0.0 {
+            scale
+        } else {
+            1.0
+        }
+    }
+
+    // World rect seen through the current 2D camera and orthographic
+    // projection, including the render-only effects
+    fn get_view_rect(&self) -> Option<Rectangle> {
+        let camera = self.render_camera?;
+        let proj = self.render_proj?;
+        if !matches!(camera, Camera::Cam2D { .. })
+            || !matches!(proj, Projection::Orthographic { .. })
+        {
+            return None;
         }
+
+        let window_size = self.get_window_size();
+        let (width, height) = (window_size.0 as f32, window_size.1 as f32);
+        let corners = [
+            point![0.0, 0.0],
+            point![width, 0.0],
+            point![width, height],
+            point![0.0, height],
+        ]
+        .map(|p| camera.screen_to_world(&proj, window_size, p));
+
+        Some(Rectangle::new(
+            corners.iter().fold(corners[0], |min, p| min.inf(p)),
+            corners.iter().fold(corners[0], |max, p| max.sup(p)),
+        ))
+    }
+
+    fn get_camera_and_proj(&self) -> (Camera, Projection) {
+        let camera = self.get_camera().expect(
+            "Renderer doesn't have camera. Call `renderer.set_camera` first",
+        );
+        let proj = self.get_proj().expect(
+            "Renderer doesn't have projection. Call `renderer.set_proj` first",
+        );
+
+        (camera, proj)
     }
 
     pub fn set_screen_proj(&mut self) {
@@ -910,12 +2212,23 @@ impl Renderer {
     }
 
     pub fn set_camera(&mut self, camera: Camera) {
+        self.set_render_camera(camera, camera);
+    }
+
+    // Same as `set_render_proj`, but for the camera
+    pub fn set_render_camera(
+        &mut self,
+        camera: Camera,
+        render_camera: Camera,
+    ) {
+        self.camera = Some(camera);
+        self.render_camera = Some(render_camera);
         let curr_camera = self.get_curr_draw_call().camera;
         if curr_camera.is_none()
-            || curr_camera.is_some_and(|p| p != camera)
+            || curr_camera.is_some_and(|p| p != render_camera)
         {
             let draw_call = self.get_new_draw_call();
-            draw_call.camera = Some(camera);
+            draw_call.camera = Some(render_camera);
         }
     }
 
@@ -933,6 +2246,12 @@ impl Renderer {
         }
     }
 
+    pub fn set_fog(&mut self, fog: Option<Fog>) {
+        if self.get_curr_draw_call().fog != fog {
+            self.get_new_draw_call().fog = fog;
+        }
+    }
+
     pub fn set_tex(&mut self, tex: Texture, is_font: bool) {
         let curr_tex = self.get_curr_draw_call().tex;
         if curr_tex.is_none() || curr_tex.is_some_and(|t| t != tex) {
@@ -958,12 +2277,15 @@ impl Renderer {
     fn get_new_draw_call(&mut self) -> &mut DrawCall {
         if self.draw_calls.len() == 0 {
             self.draw_calls.push(DrawCall::default());
-        } else if self.get_curr_draw_call().n_vertices != 0 {
+        } else if self.get_curr_draw_call().n_vertices != 0
+            || self.get_curr_draw_call().is_whole_vb
+        {
             let curr = self.get_curr_draw_call().clone();
             let new = DrawCall {
                 vb_idx: 0,
                 from_vertex: self.vb_cpu.get_n_vertcies(),
                 n_vertices: 0,
+                is_whole_vb: false,
                 tex: curr.tex,
                 transform: None,
                 camera: curr.camera,
@@ -971,6 +2293,8 @@ impl Renderer {
                 material: curr.material,
                 is_font: curr.is_font,
                 depth_test: curr.depth_test,
+                fog: curr.fog,
+                joints: None,
             };
             self.draw_calls.push(new);
         }
@@ -1024,12 +2348,43 @@ impl Renderer {
                 true,
             );
 
+            if let Some(skybox) = self.skybox {
+                self.gl.disable(glow::DEPTH_TEST);
+                self.gl.depth_mask(false);
+
+                self.skybox_program.bind(&self.gl);
+                self.skybox_program.set_uniform_matrix_4_f32(
+                    &self.gl,
+                    "u_inv_view_proj_mat",
+                    skybox.inv_view_proj_mat.as_slice(),
+                );
+                self.skybox_program.set_uniform_1_i32(
+                    &self.gl,
+                    "u_cubemap",
+                    1,
+                );
+                self.gl.active_texture(glow::TEXTURE1);
+                skybox.cubemap.bind(&self.gl);
+                self.gl.active_texture(glow::TEXTURE0);
+                self.gl.draw_arrays(glow::TRIANGLE_STRIP, 0, 4);
+
+                self.gl.depth_mask(true);
+            }
+
             self.program.bind(&self.gl);
+            self.program.set_uniform_1_i32(&self.gl, "u_env_tex", 1);
 
             let mut curr_vb_idx = None;
             let mut curr_tex = None;
+            self.stats = RenderStats::default();
 
             for draw_call in self.draw_calls.iter() {
+                if self.frustum_culling && self.is_culled(draw_call) {
+                    self.stats.n_culled_draw_calls += 1;
+                    continue;
+                }
+                self.stats.n_draw_calls += 1;
+
                 if draw_call.depth_test {
                     self.gl.enable(glow::DEPTH_TEST);
                 } else {
@@ -1037,10 +2392,8 @@ impl Renderer {
                 }
 
                 let vb_idx = draw_call.vb_idx;
-                let model_mat = draw_call
-                    .transform
-                    .as_ref()
-                    .map_or(Matrix4::identity(), |t| t.get_mat());
+                let model_mat =
+                    draw_call.transform.unwrap_or(Matrix4::identity());
                 let view_mat = if let Some(camera) =
                     draw_call.camera.as_ref()
                 {
@@ -1071,18 +2424,20 @@ impl Renderer {
                     proj_mat.as_slice(),
                 );
 
-                if let Some(camera) = draw_call.camera {
-                    match camera {
-                        Camera::Cam3D { position, .. } => {
-                            self.program.set_uniform_3_f32(
-                                &self.gl,
-                                "u_camera_pos",
-                                position.coords.as_ref(),
-                            );
-                        }
-                        _ => {}
+                // Set for every draw call, so fog and reflections of the
+                // 2D ones don't depend on the previous 3D camera
+                let camera_pos = match draw_call.camera {
+                    Some(Camera::Cam3D { position, .. }) => position,
+                    Some(Camera::Cam2D { position, .. }) => {
+                        point![position.x, position.y, 0.0]
                     }
-                }
+                    None => Point3::origin(),
+                };
+                self.program.set_uniform_3_f32(
+                    &self.gl,
+                    "u_camera_pos",
+                    camera_pos.coords.as_ref(),
+                );
 
                 for (i, light) in self.lights.iter().enumerate() {
                     self.program.set_uniform_3_f32(
@@ -1130,21 +2485,91 @@ impl Renderer {
                     }
                 }
 
-                match draw_call.material {
+                if let Some((from_joint, n_joints)) = draw_call.joints {
+                    let joint_mats = &self.joint_mats
+                        [from_joint..from_joint + n_joints];
+                    let data: Vec<f32> = joint_mats
+                        .iter()
+                        .flat_map(|m| m.as_slice().to_owned())
+                        .collect();
+                    self.gl.bind_buffer(
+                        glow::UNIFORM_BUFFER,
+                        Some(self.joints_ubo),
+                    );
+                    self.gl.buffer_sub_data_u8_slice(
+                        glow::UNIFORM_BUFFER,
+                        0,
+                        cast_slice_to_u8(&data),
+                    );
+                }
+                let (fog_mode, fog_params) = match draw_call.fog {
+                    None => (0, [0.0; 3]),
+                    Some(Fog { mode, .. }) => match mode {
+                        FogMode::Linear { start, end } => {
+                            (1, [start, end, 0.0])
+                        }
+                        FogMode::Exponential { density } => {
+                            (2, [density, 0.0, 0.0])
+                        }
+                        FogMode::Height {
+                            density,
+                            base_height,
+                            falloff,
+                        } => (3, [density, base_height, falloff]),
+                    },
+                };
+                self.program.set_uniform_1_u32(
+                    &self.gl,
+                    "u_fog_mode",
+                    fog_mode,
+                );
+                self.program.set_uniform_3_f32(
+                    &self.gl,
+                    "u_fog_params",
+                    &fog_params,
+                );
+                if let Some(fog) = draw_call.fog {
+                    self.program.set_uniform_3_f32(
+                        &self.gl,
+                        "u_fog_color",
+                        &fog.color.as_rgb_arr(),
+                    );
+                }
+
+                self.program.set_uniform_1_u32(
+                    &self.gl,
+                    "u_is_skinned",
+                    draw_call.joints.is_some() as u32,
+                );
+
+                let (shininess, reflectivity) = match draw_call.material {
+                    Material::VertexColor => (None, 0.0),
                     Material::BlinnPhong { shininess } => {
-                        self.program.set_uniform_1_u32(
-                            &self.gl,
-                            "u_is_blinn_phong",
-                            1,
-                        );
-                        self.program.set_uniform_1_f32(
-                            &self.gl,
-                            "u_shininess",
-                            shininess,
-                        );
+                        (Some(shininess), 0.0)
                     }
-                    _ => {}
-                }
+                    Material::Reflective { shininess, reflectivity } => {
+                        (Some(shininess), reflectivity)
+                    }
+                };
+                self.program.set_uniform_1_u32(
+                    &self.gl,
+                    "u_is_blinn_phong",
+                    shininess.is_some() as u32,
+                );
+                self.program.set_uniform_1_f32(
+                    &self.gl,
+                    "u_shininess",
+                    shininess.unwrap_or(0.0),
+                );
+                self.program.set_uniform_1_f32(
+                    &self.gl,
+                    "u_reflectivity",
+                    if self.skybox.is_some() {
+                        reflectivity
+                    } else {
+                        0.0
+                    },
+                );
 
                 let vb = &mut self.vertex_buffers[vb_idx];
                 if let Some(indices_vbo) = vb.indices_vbo {
@@ -1159,10 +2584,20 @@ impl Renderer {
                         0,
                     );
                 } else {
+                    // Slices are clamped to the buffer, which could
+                    // shrink after they were queued
+                    let n_vertices = if draw_call.is_whole_vb {
+                        vb.n_vertices
+                    } else {
+                        draw_call.n_vertices.min(
+                            vb.n_vertices
+                                .saturating_sub(draw_call.from_vertex),
+                        )
+                    };
                     self.gl.draw_arrays(
                         glow::TRIANGLES,
                         draw_call.from_vertex as i32,
-                        draw_call.n_vertices as i32,
+                        n_vertices as i32,
                     );
                 }
             }
@@ -1213,6 +2648,33 @@ impl Renderer {
         self.draw_calls.clear();
         self.vb_cpu.clear();
         self.lights.clear();
+        self.joint_mats.clear();
+        self.skybox = None;
+
+        self.delete_freed_resources();
+    }
+
+    fn is_culled(&self, draw_call: &DrawCall) -> bool {
+        // Immediate geometry and skinned meshes (which can leave their
+        // bind pose bounds) are never culled
+        if draw_call.vb_idx == 0 || draw_call.joints.is_some() {
+            return false;
+        }
+
+        let (Some(camera), Some(proj)) =
+            (draw_call.camera, draw_call.proj)
+        else {
+            return false;
+        };
+
+        let vb = &self.vertex_buffers[draw_call.vb_idx];
+        let model_mat = draw_call.transform.unwrap_or(Matrix4::identity());
+        let frustum = Frustum::new(&camera, &proj);
+
+        !frustum
+            .intersects_sphere(&vb.bounding_sphere.transform(&model_mat))
+            || !frustum
+                .intersects_box(&vb.bounding_box.transform(&model_mat))
     }
 
     pub fn swap_window(&self) {
@@ -1220,6 +2682,59 @@ impl Renderer {
     }
 }
 
+fn equirect_to_cubemap_faces(
+    image: &RgbaImage,
+    size: u32,
+) -> [Vec<u8>; 6] {
+    let (width, height) = (image.width() as f32, image.height() as f32);
+    let sample = |x: f32, y: f32| -> [f32; 4] {
+        let x = x.rem_euclid(width) as u32;
+        let y = (y.max(0.0) as u32).min(image.height() - 1);
+        image
+            .get_pixel(x.min(image.width() - 1), y)
+            .0
+            .map(|c| c as f32)
+    };
+
+    [0, 1, 2, 3, 4, 5].map(|face| {
+        let mut pixels = Vec::with_capacity((size * size * 4) as usize);
+        for row in 0..size {
+            for col in 0..size {
+                let u = 2.0 * (col as f32 + 0.5) / size as f32 - 1.0;
+                let v = 2.0 * (row as f32 + 0.5) / size as f32 - 1.0;
+                let dir = match face {
+                    0 => vector![1.0, -v, -u],
+                    1 => vector![-1.0, -v, u],
+                    2 => vector![u, 1.0, v],
+                    3 => vector![u, -1.0, -v],
+                    4 => vector![u, -v, 1.0],
+                    _ => vector![-u, -v, -1.0],
+                }
+                .normalize();
+
+                // Bilinear sample of the panorama
+                let x =
+                    (0.5 + dir.x.atan2(-dir.z) / (2.0 * PI)) * width - 0.5;
+                let y = dir.y.clamp(-1.0, 1.0).acos() / PI * height - 0.5;
+                let (x0, y0) = (x.floor(), y.floor());
+                let (kx, ky) = (x - x0, y - y0);
+                let c00 = sample(x0, y0);
+                let c10 = sample(x0 + 1.0, y0);
+                let c01 = sample(x0, y0 + 1.0);
+                let c11 = sample(x0 + 1.0, y0 + 1.0);
+                for i in 0..4 {
+                    let top = c00[i] * (1.0 - kx) + c10[i] * kx;
+                    let bot = c01[i] * (1.0 - kx) + c11[i] * kx;
+                    pixels
+                        .push((top * (1.0 - ky) + bot * ky).round() as u8);
+                }
+            }
+        }
+
+        pixels
+    })
+}
+
 fn cast_slice_to_u8<T>(slice: &[T]) -> &[u8] {
     unsafe {
         core::slice::from_raw_parts(
@@ -1274,6 +2789,339 @@ fn create_attrib_vbo<T>(
     }
 }
 
+fn update_vbo<T>(
+    gl: &glow::Context,
+    target: u32,
+    vbo: glow::NativeBuffer,
+    data: &[T],
+) {
+    unsafe {
+        gl.bind_buffer(target, Some(vbo));
+        gl.buffer_sub_data_u8_slice(target, 0, cast_slice_to_u8(data));
+    }
+}
+
+// Internal format, format and data type
+fn get_gl_texture_format(format: TextureFormat) -> (u32, u32, u32) {
+    match format {
+        TextureFormat::Alpha8 => {
+            #[cfg(target_os = "emscripten")]
+            {
+                (glow::ALPHA, glow::ALPHA, glow::UNSIGNED_BYTE)
+            }
+
+            #[cfg(not(target_os = "emscripten"))]
+            {
+                (glow::RGBA, glow::ALPHA, glow::UNSIGNED_BYTE)
+            }
+        }
+        TextureFormat::R8 => (glow::R8, glow::RED, glow::UNSIGNED_BYTE),
+        TextureFormat::Rg8 => (glow::RG8, glow::RG, glow::UNSIGNED_BYTE),
+        TextureFormat::Rgb8 => {
+            (glow::RGB8, glow::RGB, glow::UNSIGNED_BYTE)
+        }
+        TextureFormat::Rgba8 => {
+            (glow::RGBA8, glow::RGBA, glow::UNSIGNED_BYTE)
+        }
+        TextureFormat::Srgb8 => {
+            (glow::SRGB8, glow::RGB, glow::UNSIGNED_BYTE)
+        }
+        TextureFormat::Srgb8Alpha8 => {
+            (glow::SRGB8_ALPHA8, glow::RGBA, glow::UNSIGNED_BYTE)
+        }
+        TextureFormat::R16F => (glow::R16F, glow::RED, glow::HALF_FLOAT),
+        TextureFormat::Rgba16F => {
+            (glow::RGBA16F, glow::RGBA, glow::HALF_FLOAT)
+        }
+        TextureFormat::Rgba32F => (glow::RGBA32F, glow::RGBA, glow::FLOAT),
+    }
+}
+
+fn get_gl_compressed_texture_format(
+    format: CompressedTextureFormat,
+) -> u32 {
+    use CompressedTextureFormat::*;
+
+    match format {
+        Bc1 => glow::COMPRESSED_RGBA_S3TC_DXT1_EXT,
+        Bc1Srgb => glow::COMPRESSED_SRGB_ALPHA_S3TC_DXT1_EXT,
+        Bc2 => glow::COMPRESSED_RGBA_S3TC_DXT3_EXT,
+        Bc2Srgb => glow::COMPRESSED_SRGB_ALPHA_S3TC_DXT3_EXT,
+        Bc3 => glow::COMPRESSED_RGBA_S3TC_DXT5_EXT,
+        Bc3Srgb => glow::COMPRESSED_SRGB_ALPHA_S3TC_DXT5_EXT,
+        Bc4 => glow::COMPRESSED_RED_RGTC1,
+        Bc5 => glow::COMPRESSED_RG_RGTC2,
+        Bc6H => glow::COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT,
+        Bc6HSigned => glow::COMPRESSED_RGB_BPTC_SIGNED_FLOAT,
+        Bc7 => glow::COMPRESSED_RGBA_BPTC_UNORM,
+        Bc7Srgb => glow::COMPRESSED_SRGB_ALPHA_BPTC_UNORM,
+        Etc2Rgb8 => glow::COMPRESSED_RGB8_ETC2,
+        Etc2Srgb8 => glow::COMPRESSED_SRGB8_ETC2,
+        Etc2Rgba8 => glow::COMPRESSED_RGBA8_ETC2_EAC,
+        Etc2Srgb8Alpha8 => glow::COMPRESSED_SRGB8_ALPHA8_ETC2_EAC,
+        Astc4x4 => glow::COMPRESSED_RGBA_ASTC_4x4_KHR,
+        Astc4x4Srgb => glow::COMPRESSED_SRGB8_ALPHA8_ASTC_4x4_KHR,
+    }
+}
+
+// Suffix of the extension which provides the format, None if it's the
+// core one
+fn get_compressed_format_extension(
+    format: CompressedTextureFormat,
+) -> Option<&'static str> {
+    use CompressedTextureFormat::*;
+
+    #[cfg(target_os = "emscripten")]
+    {
+        match format {
+            Bc1 | Bc2 | Bc3 => Some("compressed_texture_s3tc"),
+            Bc1Srgb | Bc2Srgb | Bc3Srgb => {
+                Some("compressed_texture_s3tc_srgb")
+            }
+            Bc4 | Bc5 => Some("texture_compression_rgtc"),
+            Bc6H | Bc6HSigned | Bc7 | Bc7Srgb => {
+                Some("texture_compression_bptc")
+            }
+            Etc2Rgb8 | Etc2Srgb8 | Etc2Rgba8 | Etc2Srgb8Alpha8 => {
+                Some("compressed_texture_etc")
+            }
+            Astc4x4 | Astc4x4Srgb => Some("compressed_texture_astc"),
+        }
+    }
+
+    #[cfg(not(target_os = "emscripten"))]
+    {
+        match format {
+            Bc1 | Bc1Srgb | Bc2 | Bc2Srgb | Bc3 | Bc3Srgb => {
+                Some("texture_compression_s3tc")
+            }
+            Astc4x4 | Astc4x4Srgb => Some("texture_compression_astc_ldr"),
+            _ => None,
+        }
+    }
+}
+
+// The largest alignment (up to 8) which the row size is multiple of, so
+// the tightly packed rows are read correctly
+fn get_unpack_alignment(row_size: usize) -> i32 {
+    [8, 4, 2]
+        .into_iter()
+        .find(|alignment| row_size.is_multiple_of(*alignment))
+        .unwrap_or(1) as i32
+}
+
+// Format of the mip levels read from the KTX2 or DDS file
+#[derive(Debug, Clone, Copy)]
+enum LevelFormat {
+    Uncompressed(TextureFormat),
+    Compressed(CompressedTextureFormat),
+}
+
+impl LevelFormat {
+    fn get_level_size(&self, width: u32, height: u32) -> usize {
+        match self {
+            Self::Uncompressed(format) => {
+                (width * height) as usize * format.get_pixel_size()
+            }
+            Self::Compressed(format) => {
+                format.get_level_size(width, height)
+            }
+        }
+    }
+}
+
+fn get_ktx2_level_format(format: ktx2::Format) -> Option<LevelFormat> {
+    use ktx2::Format;
+    use CompressedTextureFormat::*;
+    use LevelFormat::*;
+
+    let format = match format {
+        Format::R8_UNORM => Uncompressed(TextureFormat::R8),
+        Format::R8G8_UNORM => Uncompressed(TextureFormat::Rg8),
+        Format::R8G8B8_UNORM => Uncompressed(TextureFormat::Rgb8),
+        Format::R8G8B8_SRGB => Uncompressed(TextureFormat::Srgb8),
+        Format::R8G8B8A8_UNORM => Uncompressed(TextureFormat::Rgba8),
+        Format::R8G8B8A8_SRGB => Uncompressed(TextureFormat::Srgb8Alpha8),
+        Format::R16_SFLOAT => Uncompressed(TextureFormat::R16F),
+        Format::R16G16B16A16_SFLOAT => {
+            Uncompressed(TextureFormat::Rgba16F)
+        }
+        Format::R32G32B32A32_SFLOAT => {
+            Uncompressed(TextureFormat::Rgba32F)
+        }
+        Format::BC1_RGB_UNORM_BLOCK | Format::BC1_RGBA_UNORM_BLOCK => {
+            Compressed(Bc1)
+        }
+        Format::BC1_RGB_SRGB_BLOCK | Format::BC1_RGBA_SRGB_BLOCK => {
+            Compressed(Bc1Srgb)
+        }
+        Format::BC2_UNORM_BLOCK => Compressed(Bc2),
+        Format::BC2_SRGB_BLOCK => Compressed(Bc2Srgb),
+        Format::BC3_UNORM_BLOCK => Compressed(Bc3),
+        Format::BC3_SRGB_BLOCK => Compressed(Bc3Srgb),
+        Format::BC4_UNORM_BLOCK => Compressed(Bc4),
+        Format::BC5_UNORM_BLOCK => Compressed(Bc5),
+        Format::BC6H_UFLOAT_BLOCK => Compressed(Bc6H),
+        Format::BC6H_SFLOAT_BLOCK => Compressed(Bc6HSigned),
+        Format::BC7_UNORM_BLOCK => Compressed(Bc7),
+        Format::BC7_SRGB_BLOCK => Compressed(Bc7Srgb),
+        Format::ETC2_R8G8B8_UNORM_BLOCK => Compressed(Etc2Rgb8),
+        Format::ETC2_R8G8B8_SRGB_BLOCK => Compressed(Etc2Srgb8),
+        Format::ETC2_R8G8B8A8_UNORM_BLOCK => Compressed(Etc2Rgba8),
+        Format::ETC2_R8G8B8A8_SRGB_BLOCK => Compressed(Etc2Srgb8Alpha8),
+        Format::ASTC_4x4_UNORM_BLOCK => Compressed(Astc4x4),
+        Format::ASTC_4x4_SRGB_BLOCK => Compressed(Astc4x4Srgb),
+        _ => return None,
+    };
+
+    Some(format)
+}
+
+fn get_dxgi_level_format(
+    format: ddsfile::DxgiFormat,
+) -> Option<LevelFormat> {
+    use ddsfile::DxgiFormat;
+    use CompressedTextureFormat::*;
+    use LevelFormat::*;
+
+    let format = match format {
+        DxgiFormat::R8_UNorm => Uncompressed(TextureFormat::R8),
+        DxgiFormat::R8G8_UNorm => Uncompressed(TextureFormat::Rg8),
+        DxgiFormat::R8G8B8A8_UNorm => Uncompressed(TextureFormat::Rgba8),
+        DxgiFormat::R8G8B8A8_UNorm_sRGB => {
+            Uncompressed(TextureFormat::Srgb8Alpha8)
+        }
+        DxgiFormat::R16_Float => Uncompressed(TextureFormat::R16F),
+        DxgiFormat::R16G16B16A16_Float => {
+            Uncompressed(TextureFormat::Rgba16F)
+        }
+        DxgiFormat::R32G32B32A32_Float => {
+            Uncompressed(TextureFormat::Rgba32F)
+        }
+        DxgiFormat::BC1_UNorm => Compressed(Bc1),
+        DxgiFormat::BC1_UNorm_sRGB => Compressed(Bc1Srgb),
+        DxgiFormat::BC2_UNorm => Compressed(Bc2),
+        DxgiFormat::BC2_UNorm_sRGB => Compressed(Bc2Srgb),
+        DxgiFormat::BC3_UNorm => Compressed(Bc3),
+        DxgiFormat::BC3_UNorm_sRGB => Compressed(Bc3Srgb),
+        DxgiFormat::BC4_UNorm => Compressed(Bc4),
+        DxgiFormat::BC5_UNorm => Compressed(Bc5),
+        DxgiFormat::BC6H_UF16 => Compressed(Bc6H),
+        DxgiFormat::BC6H_SF16 => Compressed(Bc6HSigned),
+        DxgiFormat::BC7_UNorm => Compressed(Bc7),
+        DxgiFormat::BC7_UNorm_sRGB => Compressed(Bc7Srgb),
+        _ => return None,
+    };
+
+    Some(format)
+}
+
+// Legacy DDS files without the DX10 header
+fn get_d3d_level_format(
+    format: ddsfile::D3DFormat,
+) -> Option<LevelFormat> {
+    use ddsfile::D3DFormat;
+    use CompressedTextureFormat::*;
+    use LevelFormat::*;
+
+    let format = match format {
+        D3DFormat::L8 => Uncompressed(TextureFormat::R8),
+        D3DFormat::A8B8G8R8 => Uncompressed(TextureFormat::Rgba8),
+        D3DFormat::R16F => Uncompressed(TextureFormat::R16F),
+        D3DFormat::A16B16G16R16F => Uncompressed(TextureFormat::Rgba16F),
+        D3DFormat::A32B32G32R32F => Uncompressed(TextureFormat::Rgba32F),
+        D3DFormat::DXT1 => Compressed(Bc1),
+        D3DFormat::DXT2 | D3DFormat::DXT3 => Compressed(Bc2),
+        D3DFormat::DXT4 | D3DFormat::DXT5 => Compressed(Bc3),
+        _ => return None,
+    };
+
+    Some(format)
+}
+
+// Applies the options to the texture bound to TEXTURE_2D. Mip levels are
+// generated only if `generate_mipmaps` is set
+fn set_texture_options(
+    gl: &glow::Context,
+    options: &TextureOptions,
+    generate_mipmaps: bool,
+) {
+    use TextureFilter::*;
+
+    let get_filter = |filter| match filter {
+        Nearest => glow::NEAREST,
+        Linear => glow::LINEAR,
+    };
+    let get_wrap = |wrap| match wrap {
+        TextureWrap::Repeat => glow::REPEAT,
+        TextureWrap::MirroredRepeat => glow::MIRRORED_REPEAT,
+        TextureWrap::ClampToEdge => glow::CLAMP_TO_EDGE,
+    };
+    let min_filter = match (options.min_filter, options.mipmap_filter) {
+        (filter, None) => get_filter(filter),
+        (Nearest, Some(Nearest)) => glow::NEAREST_MIPMAP_NEAREST,
+        (Nearest, Some(Linear)) => glow::NEAREST_MIPMAP_LINEAR,
+        (Linear, Some(Nearest)) => glow::LINEAR_MIPMAP_NEAREST,
+        (Linear, Some(Linear)) => glow::LINEAR_MIPMAP_LINEAR,
+    };
+
+    let params = [
+        (glow::TEXTURE_WRAP_S, get_wrap(options.wrap_s)),
+        (glow::TEXTURE_WRAP_T, get_wrap(options.wrap_t)),
+        (glow::TEXTURE_MAG_FILTER, get_filter(options.mag_filter)),
+        (glow::TEXTURE_MIN_FILTER, min_filter),
+    ];
+
+    unsafe {
+        for (param, value) in params {
+            gl.tex_parameter_i32(glow::TEXTURE_2D, param, value as i32);
+        }
+
+        if generate_mipmaps && options.mipmap_filter.is_some() {
+            gl.generate_mipmap(glow::TEXTURE_2D);
+        }
+
+        if is_anisotropy_supported(gl) {
+            let max_anisotropy =
+                gl.get_parameter_f32(glow::MAX_TEXTURE_MAX_ANISOTROPY);
+            gl.tex_parameter_f32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MAX_ANISOTROPY,
+                options.anisotropy.clamp(1.0, max_anisotropy.max(1.0)),
+            );
+        }
+    }
+}
+
+// Anisotropic filtering is the core feature since GL 4.6 and the
+// extension in GLES
+fn is_anisotropy_supported(gl: &glow::Context) -> bool {
+    #[cfg(target_os = "emscripten")]
+    {
+        gl.supported_extensions()
+            .iter()
+            .any(|e| e.ends_with("EXT_texture_filter_anisotropic"))
+    }
+
+    #[cfg(not(target_os = "emscripten"))]
+    {
+        let _ = gl;
+        true
+    }
+}
+
+fn realloc_vbo(
+    gl: &glow::Context,
+    target: u32,
+    vbo: glow::NativeBuffer,
+    data: &[u8],
+) {
+    unsafe {
+        gl.bind_buffer(target, Some(vbo));
+        gl.buffer_data_u8_slice(target, data, glow::DYNAMIC_DRAW);
+    }
+}
+
 fn create_indices_vbo(
     gl: &glow::Context,
     data: &[u32],
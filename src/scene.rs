@@ -0,0 +1,188 @@
+use crate::common::*;
+use nalgebra::{Matrix4, Point3};
+
+#[derive(Clone, Debug)]
+pub struct SceneNode {
+    pub transform: Transformation,
+    pub mesh: Option<VertexBufferIdx>,
+    pub material: Material,
+    pub light: Option<Light>,
+
+    parent: Option<usize>,
+    children: Vec<usize>,
+}
+
+impl SceneNode {
+    pub fn new(transform: Transformation) -> Self {
+        Self {
+            transform,
+            mesh: None,
+            material: Material::default(),
+            light: None,
+            parent: None,
+            children: vec![],
+        }
+    }
+
+    pub fn get_parent(&self) -> Option<usize> {
+        self.parent
+    }
+
+    pub fn get_children(&self) -> &[usize] {
+        &self.children
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Scene {
+    nodes: Vec<Option<SceneNode>>,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self { nodes: Vec::with_capacity(128) }
+    }
+
+    pub fn add_node(
+        &mut self,
+        parent: Option<usize>,
+        transform: Transformation,
+    ) -> usize {
+        let idx = self.nodes.len();
+        self.nodes.push(Some(SceneNode::new(transform)));
+        self.set_parent(idx, parent);
+
+        idx
+    }
+
+    pub fn add_mesh_node(
+        &mut self,
+        parent: Option<usize>,
+        transform: Transformation,
+        vb_idx: VertexBufferIdx,
+        material: Material,
+    ) -> usize {
+        let idx = self.add_node(parent, transform);
+        let node = self.get_node_mut(idx);
+        node.mesh = Some(vb_idx);
+        node.material = material;
+
+        idx
+    }
+
+    pub fn add_light_node(
+        &mut self,
+        parent: Option<usize>,
+        transform: Transformation,
+        light: Light,
+    ) -> usize {
+        let idx = self.add_node(parent, transform);
+        self.get_node_mut(idx).light = Some(light);
+
+        idx
+    }
+
+    pub fn remove_node(&mut self, idx: usize) {
+        self.set_parent(idx, None);
+
+        let mut stack = vec![idx];
+        while let Some(idx) = stack.pop() {
+            if let Some(node) = self.nodes[idx].take() {
+                stack.extend(node.children);
+            }
+        }
+    }
+
+    pub fn set_parent(&mut self, idx: usize, parent: Option<usize>) {
+        if let Some(parent) = parent {
+            if self.is_ancestor(idx, parent) {
+                panic!("Can't attach the scene node {} to its own descendant {}", idx, parent);
+            }
+        }
+
+        if let Some(old_parent) = self.get_node(idx).parent {
+            self.get_node_mut(old_parent).children.retain(|&c| c != idx);
+        }
+
+        if let Some(parent) = parent {
+            self.get_node_mut(parent).children.push(idx);
+        }
+
+        self.get_node_mut(idx).parent = parent;
+    }
+
+    pub fn has_node(&self, idx: usize) -> bool {
+        self.nodes.get(idx).is_some_and(|n| n.is_some())
+    }
+
+    pub fn get_node(&self, idx: usize) -> &SceneNode {
+        self.nodes[idx]
+            .as_ref()
+            .unwrap_or_else(|| panic!("Scene node {} was removed", idx))
+    }
+
+    pub fn get_node_mut(&mut self, idx: usize) -> &mut SceneNode {
+        self.nodes[idx]
+            .as_mut()
+            .unwrap_or_else(|| panic!("Scene node {} was removed", idx))
+    }
+
+    pub fn get_roots(&self) -> impl Iterator<Item = usize> + '_ {
+        self.nodes.iter().enumerate().filter_map(|(idx, node)| {
+            node.as_ref().and_then(|n| {
+                if n.parent.is_none() {
+                    Some(idx)
+                } else {
+                    None
+                }
+            })
+        })
+    }
+
+    pub fn get_world_mat(&self, idx: usize) -> Matrix4<f32> {
+        let mut mat = Matrix4::identity();
+        let mut curr = Some(idx);
+        while let Some(idx) = curr {
+            let node = self.get_node(idx);
+            mat = node.transform.get_mat() * mat;
+            curr = node.parent;
+        }
+
+        mat
+    }
+
+    pub fn get_world_position(&self, idx: usize) -> Point3<f32> {
+        self.get_world_mat(idx).transform_point(&Point3::origin())
+    }
+
+    pub fn get_world_mats(&self) -> Vec<(usize, Matrix4<f32>)> {
+        let mut world_mats = Vec::with_capacity(self.nodes.len());
+        let mut stack: Vec<(usize, Matrix4<f32>)> = self
+            .get_roots()
+            .map(|idx| (idx, Matrix4::identity()))
+            .collect();
+
+        while let Some((idx, parent_mat)) = stack.pop() {
+            let node = self.get_node(idx);
+            let mat = parent_mat * node.transform.get_mat();
+            for &child in node.children.iter() {
+                stack.push((child, mat));
+            }
+            world_mats.push((idx, mat));
+        }
+
+        world_mats
+    }
+
+    fn is_ancestor(&self, ancestor: usize, idx: usize) -> bool {
+        let mut curr = Some(idx);
+        while let Some(idx) = curr {
+            if idx == ancestor {
+                return true;
+            }
+            curr = self.get_node(idx).parent;
+        }
+
+        false
+    }
+}
@@ -1,29 +1,17 @@
 use crate::common::*;
 use std::ops::AddAssign;
 
+use core::f32::consts::PI;
 use nalgebra::{Point2, Point3, Vector2};
 
-pub const CIRCLE_N_TRIANGLES: usize = 16;
-const UNIT_CIRCLE_POINTS: [Point2<f32>; CIRCLE_N_TRIANGLES] = [
-    Point2::new(1.0, 0.0),
-    Point2::new(0.9238795325112867, 0.3826834323650898),
-    Point2::new(0.7071067811865475, 0.7071067811865476),
-    Point2::new(0.38268343236508967, 0.9238795325112867),
-    Point2::new(0.0, 1.0),
-    Point2::new(-0.3826834323650898, 0.9238795325112867),
-    Point2::new(-0.7071067811865476, 0.7071067811865475),
-    Point2::new(-0.9238795325112867, 0.38268343236508967),
-    Point2::new(-1.0, 0.0),
-    Point2::new(-0.9238795325112867, -0.3826834323650898),
-    Point2::new(-0.7071067811865475, -0.7071067811865476),
-    Point2::new(-0.38268343236508967, -0.9238795325112867),
-    Point2::new(0.0, -1.0),
-    Point2::new(0.3826834323650898, -0.9238795325112867),
-    Point2::new(0.7071067811865476, -0.7071067811865475),
-    Point2::new(0.9238795325112867, -0.38268343236508967),
-];
-
-#[derive(Clone, Copy)]
+// Max distance (in pixels) between an arc and its chords
+const ARC_TOLERANCE: f32 = 0.25;
+const MIN_N_CIRCLE_SEGMENTS: usize = 8;
+const MAX_N_CIRCLE_SEGMENTS: usize = 256;
+const MAX_CURVE_SUBDIVISION_DEPTH: usize = 16;
+const CURVE_N_LENGTH_SAMPLES: usize = 64;
+
+#[derive(Clone, Copy, Debug)]
 pub struct Line {
     pub s: Point2<f32>,
     pub e: Point2<f32>,
@@ -35,7 +23,7 @@ impl Line {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 pub struct Triangle {
     pub a: Point3<f32>,
     pub b: Point3<f32>,
@@ -381,18 +369,1263 @@ impl Circle {
         Self { center, radius }
     }
 
-    pub fn to_triangles(&self) -> [Triangle; CIRCLE_N_TRIANGLES] {
-        let mut triangles =
-            [(); CIRCLE_N_TRIANGLES].map(|_| Triangle::default());
+    pub fn get_n_segments(&self, scale: f32) -> usize {
+        get_n_arc_segments(self.radius * scale, 2.0 * PI)
+    }
+
+    pub fn get_points(&self, n_segments: usize) -> Vec<Point2<f32>> {
+        let radii = Vector2::new(self.radius, self.radius);
+        let mut points = get_arc_points(
+            self.center,
+            radii,
+            0.0,
+            0.0,
+            2.0 * PI,
+            n_segments,
+        );
+        points.pop();
+
+        points
+    }
+
+    pub fn to_triangles(&self, n_segments: usize) -> Vec<Triangle> {
+        get_fan_triangles(self.center, &self.get_points(n_segments), true)
+    }
+
+    pub fn contains_point(&self, point: &Point2<f32>) -> bool {
+        (point - self.center).norm_squared() <= self.radius * self.radius
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ellipse {
+    pub center: Point2<f32>,
+    pub radii: Vector2<f32>,
+    pub rotation: f32,
+}
+
+impl Ellipse {
+    pub fn new(
+        center: Point2<f32>,
+        radii: Vector2<f32>,
+        rotation: f32,
+    ) -> Self {
+        Self { center, radii, rotation }
+    }
+
+    pub fn get_n_segments(&self, scale: f32) -> usize {
+        get_n_arc_segments(self.radii.max() * scale, 2.0 * PI)
+    }
+
+    pub fn get_points(&self, n_segments: usize) -> Vec<Point2<f32>> {
+        let mut points = get_arc_points(
+            self.center,
+            self.radii,
+            self.rotation,
+            0.0,
+            2.0 * PI,
+            n_segments,
+        );
+        points.pop();
+
+        points
+    }
+
+    pub fn to_triangles(&self, n_segments: usize) -> Vec<Triangle> {
+        get_fan_triangles(self.center, &self.get_points(n_segments), true)
+    }
+
+    pub fn contains_point(&self, point: &Point2<f32>) -> bool {
+        let (sin, cos) = (-self.rotation).sin_cos();
+        let d = point - self.center;
+        let x = (d.x * cos - d.y * sin) / self.radii.x;
+        let y = (d.x * sin + d.y * cos) / self.radii.y;
+
+        x * x + y * y <= 1.0
+    }
+}
+
+// Angles are in radians, counter-clockwise from the x axis
+#[derive(Clone, Copy, Debug)]
+pub struct Arc {
+    pub center: Point2<f32>,
+    pub radius: f32,
+    pub start_angle: f32,
+    pub end_angle: f32,
+}
+
+impl Arc {
+    pub fn new(
+        center: Point2<f32>,
+        radius: f32,
+        start_angle: f32,
+        end_angle: f32,
+    ) -> Self {
+        Self { center, radius, start_angle, end_angle }
+    }
+
+    pub fn get_n_segments(&self, scale: f32) -> usize {
+        let angle = (self.end_angle - self.start_angle).abs();
+
+        get_n_arc_segments(self.radius * scale, angle)
+    }
+
+    // Open polyline from the start angle to the end one
+    pub fn get_points(&self, n_segments: usize) -> Vec<Point2<f32>> {
+        get_arc_points(
+            self.center,
+            Vector2::new(self.radius, self.radius),
+            0.0,
+            self.start_angle,
+            self.end_angle,
+            n_segments,
+        )
+    }
+}
+
+// Pie slice
+#[derive(Clone, Copy, Debug)]
+pub struct Sector {
+    pub center: Point2<f32>,
+    pub radius: f32,
+    pub start_angle: f32,
+    pub end_angle: f32,
+}
+
+impl Sector {
+    pub fn new(
+        center: Point2<f32>,
+        radius: f32,
+        start_angle: f32,
+        end_angle: f32,
+    ) -> Self {
+        Self { center, radius, start_angle, end_angle }
+    }
+
+    pub fn get_arc(&self) -> Arc {
+        Arc::new(
+            self.center,
+            self.radius,
+            self.start_angle,
+            self.end_angle,
+        )
+    }
+
+    pub fn get_n_segments(&self, scale: f32) -> usize {
+        self.get_arc().get_n_segments(scale)
+    }
+
+    // Closed contour which starts at the center
+    pub fn get_points(&self, n_segments: usize) -> Vec<Point2<f32>> {
+        let mut points = vec![self.center];
+        points.extend(self.get_arc().get_points(n_segments));
+
+        points
+    }
+
+    pub fn to_triangles(&self, n_segments: usize) -> Vec<Triangle> {
+        let mut points = self.get_arc().get_points(n_segments);
+        // Clockwise sweep is reversed, so the triangles aren't culled
+        if self.end_angle < self.start_angle {
+            points.reverse();
+        }
+
+        get_fan_triangles(self.center, &points, false)
+    }
+
+    pub fn contains_point(&self, point: &Point2<f32>) -> bool {
+        let d = point - self.center;
+
+        d.norm_squared() <= self.radius * self.radius
+            && is_angle_between(
+                d.y.atan2(d.x),
+                self.start_angle,
+                self.end_angle,
+            )
+    }
+}
+
+// Annulus or its part if the angles don't cover the full turn
+#[derive(Clone, Copy, Debug)]
+pub struct Ring {
+    pub center: Point2<f32>,
+    pub inner_radius: f32,
+    pub outer_radius: f32,
+    pub start_angle: f32,
+    pub end_angle: f32,
+}
+
+impl Ring {
+    pub fn new(
+        center: Point2<f32>,
+        inner_radius: f32,
+        outer_radius: f32,
+        start_angle: f32,
+        end_angle: f32,
+    ) -> Self {
+        Self {
+            center,
+            inner_radius,
+            outer_radius,
+            start_angle,
+            end_angle,
+        }
+    }
+
+    pub fn new_full(
+        center: Point2<f32>,
+        inner_radius: f32,
+        outer_radius: f32,
+    ) -> Self {
+        Self::new(center, inner_radius, outer_radius, 0.0, 2.0 * PI)
+    }
+
+    pub fn is_full(&self) -> bool {
+        (self.end_angle - self.start_angle).abs() >= 2.0 * PI
+    }
+
+    pub fn get_inner_arc(&self) -> Arc {
+        Arc::new(
+            self.center,
+            self.inner_radius,
+            self.start_angle,
+            self.end_angle,
+        )
+    }
+
+    pub fn get_outer_arc(&self) -> Arc {
+        Arc::new(
+            self.center,
+            self.outer_radius,
+            self.start_angle,
+            self.end_angle,
+        )
+    }
+
+    pub fn get_n_segments(&self, scale: f32) -> usize {
+        self.get_outer_arc().get_n_segments(scale)
+    }
+
+    // Closed contours: the outer and the inner circles for the full ring
+    // or a single contour around the partial one
+    pub fn get_contours(
+        &self,
+        n_segments: usize,
+    ) -> Vec<Vec<Point2<f32>>> {
+        let mut outer = self.get_outer_arc().get_points(n_segments);
+        let mut inner = self.get_inner_arc().get_points(n_segments);
+
+        if self.is_full() {
+            outer.pop();
+            inner.pop();
+            inner.reverse();
+            vec![outer, inner]
+        } else {
+            inner.reverse();
+            outer.extend(inner);
+            vec![outer]
+        }
+    }
+
+    pub fn to_triangles(&self, n_segments: usize) -> Vec<Triangle> {
+        let mut outer = self.get_outer_arc().get_points(n_segments);
+        let mut inner = self.get_inner_arc().get_points(n_segments);
+        // Clockwise sweep is reversed, so the triangles aren't culled
+        if self.end_angle < self.start_angle {
+            outer.reverse();
+            inner.reverse();
+        }
+        let mut triangles = Vec::with_capacity(n_segments * 2);
 
-        let a = self.center;
-        for i in 0..CIRCLE_N_TRIANGLES {
-            let j = (i + 1) % (CIRCLE_N_TRIANGLES);
-            let b = UNIT_CIRCLE_POINTS[j] * self.radius + a.coords;
-            let c = UNIT_CIRCLE_POINTS[i] * self.radius + a.coords;
-            triangles[i] = Triangle::new_2d(c, b, a);
+        for i in 0..n_segments {
+            let (a, b) = (inner[i], inner[i + 1]);
+            let (c, d) = (outer[i], outer[i + 1]);
+            triangles.push(Triangle::new_2d(a, c, d));
+            triangles.push(Triangle::new_2d(a, d, b));
         }
 
         triangles
     }
+
+    pub fn contains_point(&self, point: &Point2<f32>) -> bool {
+        let d = point - self.center;
+        let dist = d.norm();
+
+        dist >= self.inner_radius
+            && dist <= self.outer_radius
+            && (self.is_full()
+                || is_angle_between(
+                    d.y.atan2(d.x),
+                    self.start_angle,
+                    self.end_angle,
+                ))
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RoundedRectangle {
+    pub rect: Rectangle,
+    // Bot-left, bot-right, top-right and top-left corner radii
+    pub radii: [f32; 4],
+}
+
+impl RoundedRectangle {
+    pub fn new(rect: Rectangle, radii: [f32; 4]) -> Self {
+        Self { rect, radii }
+    }
+
+    pub fn new_uniform(rect: Rectangle, radius: f32) -> Self {
+        Self::new(rect, [radius; 4])
+    }
+
+    // Radii which don't exceed the half of the rectangle sides
+    pub fn get_clamped_radii(&self) -> [f32; 4] {
+        let max_radius =
+            0.5 * self.rect.get_width().min(self.rect.get_height());
+
+        self.radii.map(|r| r.clamp(0.0, max_radius))
+    }
+
+    // Number of segments per corner
+    pub fn get_n_segments(&self, scale: f32) -> usize {
+        let radius =
+            self.get_clamped_radii().into_iter().fold(0.0, f32::max);
+
+        (get_n_arc_segments(radius * scale, 2.0 * PI) / 4).max(1)
+    }
+
+    // Counter-clockwise contour starting at the bot-left corner
+    pub fn get_points(&self, n_segments: usize) -> Vec<Point2<f32>> {
+        let radii = self.get_clamped_radii();
+        let corners = self.rect.get_vertices();
+        let dirs = [
+            Vector2::new(1.0, 1.0),
+            Vector2::new(-1.0, 1.0),
+            Vector2::new(-1.0, -1.0),
+            Vector2::new(1.0, -1.0),
+        ];
+
+        let mut points = Vec::with_capacity(4 * (n_segments + 1));
+        for i in 0..4 {
+            let radius = radii[i];
+            if radius <= 0.0 {
+                points.push(corners[i]);
+                continue;
+            }
+
+            let center = corners[i] + dirs[i] * radius;
+            let start_angle = PI + 0.5 * PI * i as f32;
+            points.extend(get_arc_points(
+                center,
+                Vector2::new(radius, radius),
+                0.0,
+                start_angle,
+                start_angle + 0.5 * PI,
+                n_segments,
+            ));
+        }
+
+        points
+    }
+
+    pub fn to_triangles(&self, n_segments: usize) -> Vec<Triangle> {
+        let points = self.get_points(n_segments);
+
+        get_fan_triangles(self.rect.get_center(), &points, true)
+    }
+
+    pub fn contains_point(&self, point: &Point2<f32>) -> bool {
+        let radii = self.get_clamped_radii();
+        let center = self.rect.get_center();
+        let half_size = self.rect.get_size() * 0.5;
+        let d = point - center;
+
+        let radius = match (d.x >= 0.0, d.y >= 0.0) {
+            (false, false) => radii[0],
+            (true, false) => radii[1],
+            (true, true) => radii[2],
+            (false, true) => radii[3],
+        };
+
+        // Distance to the rectangle shrinked by the corner radius
+        let q = d.abs() - half_size + Vector2::new(radius, radius);
+        q.x.max(q.y).min(0.0) + q.sup(&Vector2::zeros()).norm() <= radius
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct QuadraticBezier {
+    pub p0: Point2<f32>,
+    pub p1: Point2<f32>,
+    pub p2: Point2<f32>,
+}
+
+impl QuadraticBezier {
+    pub fn new(p0: Point2<f32>, p1: Point2<f32>, p2: Point2<f32>) -> Self {
+        Self { p0, p1, p2 }
+    }
+
+    pub fn get_point(&self, t: f32) -> Point2<f32> {
+        let k = 1.0 - t;
+        let p = self.p0.coords * k * k
+            + self.p1.coords * 2.0 * k * t
+            + self.p2.coords * t * t;
+
+        Point2::from(p)
+    }
+
+    pub fn get_derivative(&self, t: f32) -> Vector2<f32> {
+        ((self.p1 - self.p0) * (1.0 - t) + (self.p2 - self.p1) * t) * 2.0
+    }
+
+    // Exact degree elevation
+    pub fn to_cubic(&self) -> CubicBezier {
+        let p1 = self.p0 + (self.p1 - self.p0) * (2.0 / 3.0);
+        let p2 = self.p2 + (self.p1 - self.p2) * (2.0 / 3.0);
+
+        CubicBezier::new(self.p0, p1, p2, self.p2)
+    }
+
+    pub fn get_bounding_rect(&self) -> Rectangle {
+        let mut points = vec![self.p0, self.p2];
+
+        // Root of the derivative: (p1 - p0) + t * (p0 - 2 * p1 + p2)
+        let a = (self.p0 - self.p1) + (self.p2 - self.p1);
+        let b = self.p1 - self.p0;
+        for i in 0..2 {
+            if a[i].abs() > f32::EPSILON {
+                let t = -b[i] / a[i];
+                if t > 0.0 && t < 1.0 {
+                    points.push(self.get_point(t));
+                }
+            }
+        }
+
+        get_bounding_rect(&points)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CubicBezier {
+    pub p0: Point2<f32>,
+    pub p1: Point2<f32>,
+    pub p2: Point2<f32>,
+    pub p3: Point2<f32>,
+}
+
+impl CubicBezier {
+    pub fn new(
+        p0: Point2<f32>,
+        p1: Point2<f32>,
+        p2: Point2<f32>,
+        p3: Point2<f32>,
+    ) -> Self {
+        Self { p0, p1, p2, p3 }
+    }
+
+    pub fn get_point(&self, t: f32) -> Point2<f32> {
+        let k = 1.0 - t;
+        let p = self.p0.coords * k * k * k
+            + self.p1.coords * 3.0 * k * k * t
+            + self.p2.coords * 3.0 * k * t * t
+            + self.p3.coords * t * t * t;
+
+        Point2::from(p)
+    }
+
+    pub fn get_derivative(&self, t: f32) -> Vector2<f32> {
+        let k = 1.0 - t;
+
+        ((self.p1 - self.p0) * k * k
+            + (self.p2 - self.p1) * 2.0 * k * t
+            + (self.p3 - self.p2) * t * t)
+            * 3.0
+    }
+
+    // De Casteljau split at t
+    pub fn split(&self, t: f32) -> (CubicBezier, CubicBezier) {
+        let p01 = self.p0.lerp(&self.p1, t);
+        let p12 = self.p1.lerp(&self.p2, t);
+        let p23 = self.p2.lerp(&self.p3, t);
+        let p012 = p01.lerp(&p12, t);
+        let p123 = p12.lerp(&p23, t);
+        let p = p012.lerp(&p123, t);
+
+        (
+            CubicBezier::new(self.p0, p01, p012, p),
+            CubicBezier::new(p, p123, p23, self.p3),
+        )
+    }
+
+    pub fn get_bounding_rect(&self) -> Rectangle {
+        let mut points = vec![self.p0, self.p3];
+
+        // Roots of the derivative: a * t^2 + b * t + c
+        let a = (self.p1 - self.p2) * 3.0 + (self.p3 - self.p0);
+        let b = ((self.p0 - self.p1) + (self.p2 - self.p1)) * 2.0;
+        let c = self.p1 - self.p0;
+        for i in 0..2 {
+            for t in get_quadratic_roots(a[i], b[i], c[i]) {
+                if t > 0.0 && t < 1.0 {
+                    points.push(self.get_point(t));
+                }
+            }
+        }
+
+        get_bounding_rect(&points)
+    }
+
+    // Recursive subdivision until the curve is within the tolerance from
+    // its chord. The result includes both end points
+    pub fn flatten(&self, tolerance: f32) -> Vec<Point2<f32>> {
+        let mut points = vec![self.p0];
+        self.flatten_into(tolerance, 0, &mut points);
+
+        points
+    }
+
+    fn flatten_into(
+        &self,
+        tolerance: f32,
+        depth: usize,
+        points: &mut Vec<Point2<f32>>,
+    ) {
+        let u =
+            self.p1.coords * 3.0 - self.p0.coords * 2.0 - self.p3.coords;
+        let v =
+            self.p2.coords * 3.0 - self.p3.coords * 2.0 - self.p0.coords;
+        let flatness = u.component_mul(&u).sup(&v.component_mul(&v)).sum();
+
+        if depth >= MAX_CURVE_SUBDIVISION_DEPTH
+            || flatness <= 16.0 * tolerance * tolerance
+        {
+            points.push(self.p3);
+        } else {
+            let (a, b) = self.split(0.5);
+            a.flatten_into(tolerance, depth + 1, points);
+            b.flatten_into(tolerance, depth + 1, points);
+        }
+    }
+}
+
+// Uniform Catmull-Rom spline which passes through all of its points.
+// Open splines reflect the end points to get the end tangents
+#[derive(Clone, Debug)]
+pub struct CatmullRom {
+    pub points: Vec<Point2<f32>>,
+    pub is_closed: bool,
+}
+
+impl CatmullRom {
+    pub fn new(points: Vec<Point2<f32>>, is_closed: bool) -> Self {
+        Self { points, is_closed }
+    }
+
+    pub fn get_n_segments(&self) -> usize {
+        match (self.points.len(), self.is_closed) {
+            (0 | 1, _) => 0,
+            (n, true) => n,
+            (n, false) => n - 1,
+        }
+    }
+
+    pub fn get_segment(&self, idx: usize) -> CubicBezier {
+        let n = self.points.len() as i32;
+        let get = |i: i32| {
+            if self.is_closed {
+                self.points[i.rem_euclid(n) as usize]
+            } else if i < 0 {
+                self.points[0] + (self.points[0] - self.points[1])
+            } else if i >= n {
+                let last = self.points[n as usize - 1];
+                last + (last - self.points[n as usize - 2])
+            } else {
+                self.points[i as usize]
+            }
+        };
+
+        let i = idx as i32;
+        let (p0, p1, p2, p3) =
+            (get(i - 1), get(i), get(i + 1), get(i + 2));
+
+        CubicBezier::new(
+            p1,
+            p1 + (p2 - p0) / 6.0,
+            p2 - (p3 - p1) / 6.0,
+            p2,
+        )
+    }
+
+    pub fn get_segments(&self) -> Vec<CubicBezier> {
+        (0..self.get_n_segments())
+            .map(|i| self.get_segment(i))
+            .collect()
+    }
+
+    // Maps the spline parameter in [0, 1] to the segment and its local t
+    fn get_segment_t(&self, t: f32) -> (usize, f32) {
+        let n_segments = self.get_n_segments();
+        let t = t.clamp(0.0, 1.0) * n_segments as f32;
+        let idx = (t.floor() as usize).min(n_segments - 1);
+
+        (idx, t - idx as f32)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Curve {
+    Quadratic(QuadraticBezier),
+    Cubic(CubicBezier),
+    CatmullRom(CatmullRom),
+}
+
+impl From<QuadraticBezier> for Curve {
+    fn from(curve: QuadraticBezier) -> Self {
+        Curve::Quadratic(curve)
+    }
+}
+
+impl From<CubicBezier> for Curve {
+    fn from(curve: CubicBezier) -> Self {
+        Curve::Cubic(curve)
+    }
+}
+
+impl From<CatmullRom> for Curve {
+    fn from(curve: CatmullRom) -> Self {
+        Curve::CatmullRom(curve)
+    }
+}
+
+impl Curve {
+    pub fn get_point(&self, t: f32) -> Point2<f32> {
+        match self {
+            Curve::Quadratic(curve) => curve.get_point(t),
+            Curve::Cubic(curve) => curve.get_point(t),
+            Curve::CatmullRom(curve) => match curve.points.len() {
+                0 => Point2::origin(),
+                1 => curve.points[0],
+                _ => {
+                    let (idx, t) = curve.get_segment_t(t);
+                    curve.get_segment(idx).get_point(t)
+                }
+            },
+        }
+    }
+
+    // Derivative with respect to the curve parameter
+    pub fn get_derivative(&self, t: f32) -> Vector2<f32> {
+        match self {
+            Curve::Quadratic(curve) => curve.get_derivative(t),
+            Curve::Cubic(curve) => curve.get_derivative(t),
+            Curve::CatmullRom(curve) => {
+                if curve.points.len() < 2 {
+                    return Vector2::zeros();
+                }
+
+                let (idx, t) = curve.get_segment_t(t);
+                curve.get_segment(idx).get_derivative(t)
+                    * curve.get_n_segments() as f32
+            }
+        }
+    }
+
+    // Unit tangent. Falls back to the finite difference where the
+    // derivative vanishes (e.g. coinciding control points)
+    pub fn get_tangent(&self, t: f32) -> Vector2<f32> {
+        if let Some(tangent) = self.get_derivative(t).try_normalize(1e-6) {
+            return tangent;
+        }
+
+        let eps = 1e-3;
+        let (t0, t1) = ((t - eps).max(0.0), (t + eps).min(1.0));
+
+        (self.get_point(t1) - self.get_point(t0))
+            .try_normalize(1e-9)
+            .unwrap_or(Vector2::zeros())
+    }
+
+    // Unit tangent rotated counter-clockwise
+    pub fn get_normal(&self, t: f32) -> Vector2<f32> {
+        let tangent = self.get_tangent(t);
+
+        Vector2::new(-tangent.y, tangent.x)
+    }
+
+    pub fn get_cubic_segments(&self) -> Vec<CubicBezier> {
+        match self {
+            Curve::Quadratic(curve) => vec![curve.to_cubic()],
+            Curve::Cubic(curve) => vec![*curve],
+            Curve::CatmullRom(curve) => curve.get_segments(),
+        }
+    }
+
+    pub fn get_bounding_rect(&self) -> Rectangle {
+        match self {
+            Curve::Quadratic(curve) => curve.get_bounding_rect(),
+            Curve::Cubic(curve) => curve.get_bounding_rect(),
+            Curve::CatmullRom(curve) if curve.points.len() < 2 => {
+                get_bounding_rect(&curve.points)
+            }
+            Curve::CatmullRom(curve) => {
+                let corners: Vec<Point2<f32>> = curve
+                    .get_segments()
+                    .iter()
+                    .flat_map(|s| {
+                        let rect = s.get_bounding_rect();
+                        [rect.get_bot_left(), rect.get_top_right()]
+                    })
+                    .collect();
+
+                get_bounding_rect(&corners)
+            }
+        }
+    }
+
+    // Polyline which deviates from the curve by at most the tolerance
+    pub fn flatten(&self, tolerance: f32) -> Vec<Point2<f32>> {
+        let tolerance = tolerance.max(1e-4);
+        let mut points: Vec<Point2<f32>> = vec![];
+
+        for segment in self.get_cubic_segments() {
+            let skip = if points.is_empty() { 0 } else { 1 };
+            points
+                .extend(segment.flatten(tolerance).into_iter().skip(skip));
+        }
+
+        points
+    }
+
+    pub fn get_length(&self) -> f32 {
+        ArcLengthTable::new(self, CURVE_N_LENGTH_SAMPLES).get_length()
+    }
+}
+
+// Maps distances along the curve to the curve parameter, so points can
+// be placed (or objects moved) with the constant speed
+#[derive(Clone, Debug)]
+pub struct ArcLengthTable {
+    ts: Vec<f32>,
+    lengths: Vec<f32>,
+}
+
+impl ArcLengthTable {
+    // The number of samples is per curve segment
+    pub fn new(curve: &Curve, n_samples: usize) -> Self {
+        let n_segments = match curve {
+            Curve::CatmullRom(curve) => curve.get_n_segments().max(1),
+            _ => 1,
+        };
+        let n = n_samples.max(1) * n_segments;
+
+        let mut ts = Vec::with_capacity(n + 1);
+        let mut lengths = Vec::with_capacity(n + 1);
+        let mut prev = curve.get_point(0.0);
+        let mut length = 0.0;
+        for i in 0..=n {
+            let t = i as f32 / n as f32;
+            let point = curve.get_point(t);
+            length += (point - prev).norm();
+            prev = point;
+
+            ts.push(t);
+            lengths.push(length);
+        }
+
+        Self { ts, lengths }
+    }
+
+    pub fn get_length(&self) -> f32 {
+        self.lengths[self.lengths.len() - 1]
+    }
+
+    pub fn get_t(&self, length: f32) -> f32 {
+        let length = length.clamp(0.0, self.get_length());
+        let idx = self.lengths.partition_point(|&l| l < length);
+        if idx == 0 {
+            return self.ts[0];
+        }
+
+        let (l0, l1) = (self.lengths[idx - 1], self.lengths[idx]);
+        let (t0, t1) = (self.ts[idx - 1], self.ts[idx]);
+        if l1 - l0 <= f32::EPSILON {
+            return t1;
+        }
+
+        t0 + (t1 - t0) * (length - l0) / (l1 - l0)
+    }
+
+    // Points spaced evenly along the curve, including both end points
+    pub fn get_even_points(
+        &self,
+        curve: &Curve,
+        n_points: usize,
+    ) -> Vec<Point2<f32>> {
+        let n = n_points.max(2) - 1;
+        let length = self.get_length();
+
+        (0..=n)
+            .map(|i| {
+                let t = self.get_t(length * i as f32 / n as f32);
+                curve.get_point(t)
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Winding {
+    Clockwise,
+    CounterClockwise,
+}
+
+// Simple polygon (convex or concave) with optional holes. Holes are
+// expected to lie inside the outer contour and not to overlap
+#[derive(Clone, Debug, Default)]
+pub struct Polygon {
+    pub points: Vec<Point2<f32>>,
+    pub holes: Vec<Vec<Point2<f32>>>,
+}
+
+impl Polygon {
+    pub fn new(points: Vec<Point2<f32>>) -> Self {
+        Self { points, holes: vec![] }
+    }
+
+    pub fn new_with_holes(
+        points: Vec<Point2<f32>>,
+        holes: Vec<Vec<Point2<f32>>>,
+    ) -> Self {
+        Self { points, holes }
+    }
+
+    pub fn from_rectangle(rect: &Rectangle) -> Self {
+        Self::new(rect.get_vertices().to_vec())
+    }
+
+    pub fn translate(&self, translation: &Vector2<f32>) -> Self {
+        let translate_points = |points: &Vec<Point2<f32>>| {
+            points.iter().map(|p| p + translation).collect()
+        };
+
+        Self {
+            points: translate_points(&self.points),
+            holes: self.holes.iter().map(translate_points).collect(),
+        }
+    }
+
+    // Positive for the counter-clockwise outer contour. Holes are ignored
+    pub fn get_signed_area(&self) -> f32 {
+        get_signed_area(&self.points)
+    }
+
+    // Area of the outer contour minus the area of the holes
+    pub fn get_area(&self) -> f32 {
+        let holes_area: f32 =
+            self.holes.iter().map(|h| get_signed_area(h).abs()).sum();
+
+        self.get_signed_area().abs() - holes_area
+    }
+
+    pub fn get_winding(&self) -> Winding {
+        get_winding(&self.points)
+    }
+
+    // Makes the outer contour counter-clockwise and the holes clockwise
+    pub fn normalize_winding(&mut self) {
+        if self.get_winding() == Winding::Clockwise {
+            self.points.reverse();
+        }
+
+        for hole in self.holes.iter_mut() {
+            if get_winding(hole) == Winding::CounterClockwise {
+                hole.reverse();
+            }
+        }
+    }
+
+    pub fn get_centroid(&self) -> Point2<f32> {
+        let mut centroid = Vector2::zeros();
+        let mut area = 0.0;
+
+        let contours = std::iter::once(&self.points).map(|c| (c, 1.0));
+        let holes = self.holes.iter().map(|c| (c, -1.0));
+        for (contour, sign) in contours.chain(holes) {
+            let contour_area = get_signed_area(contour).abs() * sign;
+            centroid += get_centroid(contour).coords * contour_area;
+            area += contour_area;
+        }
+
+        if is_area_degenerate(area, &self.points) {
+            return get_centroid(&self.points);
+        }
+
+        Point2::from(centroid / area)
+    }
+
+    pub fn is_convex(&self) -> bool {
+        if !self.holes.is_empty() || self.points.len() < 3 {
+            return false;
+        }
+
+        let n = self.points.len();
+        let mut sign = 0.0;
+        for i in 0..n {
+            let a = self.points[i];
+            let b = self.points[(i + 1) % n];
+            let c = self.points[(i + 2) % n];
+            let cross = (b - a).perp(&(c - b));
+            if is_collinear(&a, &b, &c) {
+                continue;
+            } else if sign == 0.0 {
+                sign = cross.signum();
+            } else if cross.signum() != sign {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    // Even-odd rule, so points inside the holes are outside
+    pub fn contains_point(&self, point: &Point2<f32>) -> bool {
+        std::iter::once(&self.points)
+            .chain(self.holes.iter())
+            .filter(|contour| is_point_in_contour(contour, point))
+            .count()
+            % 2
+            == 1
+    }
+
+    pub fn get_bounding_rect(&self) -> Rectangle {
+        get_bounding_rect(&self.points)
+    }
+
+    pub fn get_contours(&self) -> impl Iterator<Item = &Vec<Point2<f32>>> {
+        std::iter::once(&self.points).chain(self.holes.iter())
+    }
+
+    // Ear clipping triangulation. Holes are first merged into the outer
+    // contour with bridge edges
+    pub fn get_triangles(&self) -> Vec<Triangle> {
+        let mut polygon = self.clone();
+        polygon.normalize_winding();
+
+        let mut points = polygon.points;
+        let mut holes = polygon.holes;
+        holes.retain(|h| h.len() >= 3);
+
+        // Holes which reach the farthest to the right are merged first
+        holes.sort_by(|a, b| {
+            let get_max_x = |h: &Vec<Point2<f32>>| {
+                h.iter().fold(f32::MIN, |x, p| x.max(p.x))
+            };
+            get_max_x(b).total_cmp(&get_max_x(a))
+        });
+        for i in 0..holes.len() {
+            points = merge_hole(&points, &holes[i], &holes[i + 1..]);
+        }
+
+        clip_ears(&points)
+    }
+}
+
+fn get_signed_area(points: &[Point2<f32>]) -> f32 {
+    let n = points.len();
+    let doubled_area: f32 = (0..n)
+        .map(|i| points[i].coords.perp(&points[(i + 1) % n].coords))
+        .sum();
+
+    doubled_area * 0.5
+}
+
+fn get_winding(points: &[Point2<f32>]) -> Winding {
+    if get_signed_area(points) >= 0.0 {
+        Winding::CounterClockwise
+    } else {
+        Winding::Clockwise
+    }
+}
+
+fn get_centroid(points: &[Point2<f32>]) -> Point2<f32> {
+    let n = points.len();
+    let area = get_signed_area(points);
+    if n == 0 {
+        return Point2::origin();
+    } else if is_area_degenerate(area, points) {
+        let sum: Vector2<f32> = points.iter().map(|p| p.coords).sum();
+        return Point2::from(sum / n as f32);
+    }
+
+    let mut centroid = Vector2::zeros();
+    for i in 0..n {
+        let a = points[i].coords;
+        let b = points[(i + 1) % n].coords;
+        centroid += (a + b) * a.perp(&b);
+    }
+
+    Point2::from(centroid / (6.0 * area))
+}
+
+// Tolerance is relative to the edge lengths, so it doesn't depend on the
+// coordinates scale
+fn is_collinear(
+    a: &Point2<f32>,
+    b: &Point2<f32>,
+    c: &Point2<f32>,
+) -> bool {
+    let (ab, bc) = (b - a, c - b);
+
+    ab.perp(&bc).abs() <= f32::EPSILON * ab.norm() * bc.norm()
+}
+
+// Area is compared with the bounding rect one for the same reason
+fn is_area_degenerate(area: f32, points: &[Point2<f32>]) -> bool {
+    let rect = get_bounding_rect(points);
+
+    area.abs() <= f32::EPSILON * rect.get_width() * rect.get_height()
+}
+
+fn is_point_in_contour(
+    points: &[Point2<f32>],
+    point: &Point2<f32>,
+) -> bool {
+    let n = points.len();
+    let mut is_inside = false;
+    for i in 0..n {
+        let a = points[i];
+        let b = points[(i + n - 1) % n];
+        if (a.y > point.y) != (b.y > point.y) {
+            let x = a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x);
+            if point.x < x {
+                is_inside = !is_inside;
+            }
+        }
+    }
+
+    is_inside
+}
+
+fn is_point_in_triangle(
+    p: &Point2<f32>,
+    a: &Point2<f32>,
+    b: &Point2<f32>,
+    c: &Point2<f32>,
+) -> bool {
+    (b - a).perp(&(p - a)) >= 0.0
+        && (c - b).perp(&(p - b)) >= 0.0
+        && (a - c).perp(&(p - c)) >= 0.0
+}
+
+fn is_segments_crossing(
+    a: &Point2<f32>,
+    b: &Point2<f32>,
+    c: &Point2<f32>,
+    d: &Point2<f32>,
+) -> bool {
+    let d0 = (b - a).perp(&(c - a));
+    let d1 = (b - a).perp(&(d - a));
+    let d2 = (d - c).perp(&(a - c));
+    let d3 = (d - c).perp(&(b - c));
+
+    d0 * d1 < 0.0 && d2 * d3 < 0.0
+}
+
+// Connects the rightmost hole vertex with the closest visible contour
+// vertex and walks around the hole through this bridge
+fn merge_hole(
+    points: &[Point2<f32>],
+    hole: &[Point2<f32>],
+    other_holes: &[Vec<Point2<f32>>],
+) -> Vec<Point2<f32>> {
+    let hole_idx = (0..hole.len())
+        .max_by(|&a, &b| hole[a].x.total_cmp(&hole[b].x))
+        .unwrap();
+    let m = hole[hole_idx];
+
+    let mut candidates: Vec<usize> = (0..points.len()).collect();
+    candidates.sort_by(|&a, &b| {
+        (points[a] - m)
+            .norm_squared()
+            .total_cmp(&(points[b] - m).norm_squared())
+    });
+
+    let is_visible = |p: &Point2<f32>| {
+        let contours = std::iter::once(points)
+            .chain(std::iter::once(hole))
+            .chain(other_holes.iter().map(|h| h.as_slice()));
+
+        contours.into_iter().all(|contour| {
+            let n = contour.len();
+            (0..n).all(|i| {
+                let a = contour[i];
+                let b = contour[(i + 1) % n];
+                !is_segments_crossing(&m, p, &a, &b)
+            })
+        })
+    };
+    let points_idx = candidates
+        .iter()
+        .copied()
+        .find(|&i| is_visible(&points[i]))
+        .unwrap_or(candidates[0]);
+
+    let mut merged = Vec::with_capacity(points.len() + hole.len() + 2);
+    merged.extend_from_slice(&points[..=points_idx]);
+    merged.extend_from_slice(&hole[hole_idx..]);
+    merged.extend_from_slice(&hole[..=hole_idx]);
+    merged.extend_from_slice(&points[points_idx..]);
+
+    merged
+}
+
+// Expects the counter-clockwise contour
+fn clip_ears(points: &[Point2<f32>]) -> Vec<Triangle> {
+    let mut ids: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::with_capacity(points.len());
+
+    while ids.len() >= 3 {
+        let n = ids.len();
+        let mut ear = None;
+
+        for i in 0..n {
+            let a = &points[ids[(i + n - 1) % n]];
+            let b = &points[ids[i]];
+            let c = &points[ids[(i + 1) % n]];
+            let cross = (b - a).perp(&(c - b));
+
+            // Collinear vertex can be dropped without a triangle
+            if is_collinear(a, b, c) {
+                ear = Some((i, false));
+                break;
+            } else if cross < 0.0 {
+                continue;
+            }
+
+            // Bridge edges duplicate vertices, so the coinciding ones
+            // are not checked
+            let is_ear = ids.iter().all(|&j| {
+                let p = &points[j];
+                p == a
+                    || p == b
+                    || p == c
+                    || !is_point_in_triangle(p, a, b, c)
+            });
+            if is_ear {
+                ear = Some((i, true));
+                break;
+            }
+        }
+
+        // Degenerate (e.g. self-intersecting) polygons have no ears
+        // left, so just clip some vertex to finish anyway
+        let (i, is_triangle) = ear.unwrap_or((0, true));
+        if is_triangle {
+            triangles.push(Triangle::new_2d(
+                points[ids[(i + n - 1) % n]],
+                points[ids[i]],
+                points[ids[(i + 1) % n]],
+            ));
+        }
+        ids.remove(i);
+    }
+
+    triangles
+}
+
+// Number of segments for the arc of the given radius (in pixels) and
+// angle span
+pub fn get_n_arc_segments(radius: f32, angle: f32) -> usize {
+    let k = 1.0 - ARC_TOLERANCE / radius.max(ARC_TOLERANCE);
+    let max_step = 2.0 * k.acos();
+    let min_n_segments =
+        (MIN_N_CIRCLE_SEGMENTS as f32 * angle / (2.0 * PI)).ceil();
+    let n_segments = (angle / max_step).ceil().max(min_n_segments);
+
+    (n_segments as usize).clamp(1, MAX_N_CIRCLE_SEGMENTS)
+}
+
+// Points of the (possibly rotated elliptical) arc including both ends
+fn get_arc_points(
+    center: Point2<f32>,
+    radii: Vector2<f32>,
+    rotation: f32,
+    start_angle: f32,
+    end_angle: f32,
+    n_segments: usize,
+) -> Vec<Point2<f32>> {
+    let n_segments = n_segments.max(1);
+    let step = (end_angle - start_angle) / n_segments as f32;
+    let (rot_sin, rot_cos) = rotation.sin_cos();
+
+    (0..=n_segments)
+        .map(|i| {
+            let (sin, cos) = (start_angle + step * i as f32).sin_cos();
+            let x = cos * radii.x;
+            let y = sin * radii.y;
+
+            center
+                + Vector2::new(
+                    x * rot_cos - y * rot_sin,
+                    x * rot_sin + y * rot_cos,
+                )
+        })
+        .collect()
+}
+
+fn get_fan_triangles(
+    center: Point2<f32>,
+    points: &[Point2<f32>],
+    is_closed: bool,
+) -> Vec<Triangle> {
+    let n = points.len();
+    let n_triangles = if is_closed { n } else { n.saturating_sub(1) };
+
+    (0..n_triangles)
+        .map(|i| Triangle::new_2d(points[i], points[(i + 1) % n], center))
+        .collect()
+}
+
+fn is_angle_between(angle: f32, start_angle: f32, end_angle: f32) -> bool {
+    let (start_angle, end_angle) = if start_angle <= end_angle {
+        (start_angle, end_angle)
+    } else {
+        (end_angle, start_angle)
+    };
+
+    if end_angle - start_angle >= 2.0 * PI {
+        return true;
+    }
+
+    (angle - start_angle).rem_euclid(2.0 * PI) <= end_angle - start_angle
+}
+
+fn get_bounding_rect(points: &[Point2<f32>]) -> Rectangle {
+    let Some(first) = points.first() else {
+        return Rectangle::zeros();
+    };
+
+    let (min, max) = points
+        .iter()
+        .fold((*first, *first), |(min, max), p| (min.inf(p), max.sup(p)));
+
+    Rectangle::new(min, max)
+}
+
+// Real roots of a * t^2 + b * t + c = 0
+fn get_quadratic_roots(a: f32, b: f32, c: f32) -> Vec<f32> {
+    if a.abs() <= f32::EPSILON {
+        if b.abs() <= f32::EPSILON {
+            return vec![];
+        }
+        return vec![-c / b];
+    }
+
+    let det = b * b - 4.0 * a * c;
+    if det < 0.0 {
+        return vec![];
+    }
+
+    let det_sqrt = det.sqrt();
+    vec![(-b - det_sqrt) / (2.0 * a), (-b + det_sqrt) / (2.0 * a)]
 }
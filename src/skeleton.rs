@@ -0,0 +1,250 @@
+use crate::common::*;
+use nalgebra::{Matrix4, UnitQuaternion, Vector3};
+
+#[derive(Clone, Debug)]
+pub struct Joint {
+    pub name: String,
+    pub parent: Option<usize>,
+    pub inverse_bind_mat: Matrix4<f32>,
+    pub rest_transform: Transformation,
+}
+
+impl Joint {
+    pub fn new(
+        name: &str,
+        parent: Option<usize>,
+        inverse_bind_mat: Matrix4<f32>,
+        rest_transform: Transformation,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            parent,
+            inverse_bind_mat,
+            rest_transform,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Skeleton {
+    joints: Vec<Joint>,
+}
+
+impl Skeleton {
+    pub fn new(joints: Vec<Joint>) -> Self {
+        for (idx, joint) in joints.iter().enumerate() {
+            if joint.parent.is_some_and(|p| p >= idx) {
+                panic!(
+                    "Joint {} should be placed after its parent",
+                    joint.name
+                );
+            }
+        }
+
+        Self { joints }
+    }
+
+    pub fn get_joints(&self) -> &[Joint] {
+        &self.joints
+    }
+
+    pub fn get_n_joints(&self) -> usize {
+        self.joints.len()
+    }
+
+    pub fn find_joint(&self, name: &str) -> Option<usize> {
+        self.joints.iter().position(|j| j.name == name)
+    }
+
+    pub fn get_rest_pose(&self) -> Pose {
+        Pose::new(self.joints.iter().map(|j| j.rest_transform).collect())
+    }
+
+    pub fn get_world_mats(&self, pose: &Pose) -> Vec<Matrix4<f32>> {
+        let mut world_mats: Vec<Matrix4<f32>> =
+            Vec::with_capacity(self.joints.len());
+
+        for (joint, transform) in self.joints.iter().zip(&pose.transforms)
+        {
+            let local_mat = transform.get_mat();
+            let world_mat = match joint.parent {
+                Some(parent) => world_mats[parent] * local_mat,
+                None => local_mat,
+            };
+            world_mats.push(world_mat);
+        }
+
+        world_mats
+    }
+
+    pub fn get_joint_mats(&self, pose: &Pose) -> Vec<Matrix4<f32>> {
+        self.get_world_mats(pose)
+            .iter()
+            .zip(&self.joints)
+            .map(|(world_mat, joint)| world_mat * joint.inverse_bind_mat)
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Pose {
+    pub transforms: Vec<Transformation>,
+}
+
+impl Pose {
+    pub fn new(transforms: Vec<Transformation>) -> Self {
+        Self { transforms }
+    }
+
+    pub fn blend(&self, other: &Pose, k: f32) -> Self {
+        Self::new(
+            self.transforms
+                .iter()
+                .zip(&other.transforms)
+                .map(|(a, b)| a.lerp(b, k))
+                .collect(),
+        )
+    }
+
+    pub fn blend_masked(
+        &self,
+        other: &Pose,
+        k: f32,
+        joints: &[usize],
+    ) -> Self {
+        let mut pose = self.clone();
+        for &idx in joints {
+            pose.transforms[idx] =
+                self.transforms[idx].lerp(&other.transforms[idx], k);
+        }
+
+        pose
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe<T> {
+    pub time: f32,
+    pub value: T,
+}
+
+impl<T> Keyframe<T> {
+    pub fn new(time: f32, value: T) -> Self {
+        Self { time, value }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct JointTrack {
+    pub joint: usize,
+    pub translations: Vec<Keyframe<Vector3<f32>>>,
+    pub rotations: Vec<Keyframe<UnitQuaternion<f32>>>,
+    pub scales: Vec<Keyframe<Vector3<f32>>>,
+}
+
+impl JointTrack {
+    pub fn new(joint: usize) -> Self {
+        Self { joint, ..Default::default() }
+    }
+
+    pub fn sample(&self, time: f32, transform: &mut Transformation) {
+        if let Some(translation) =
+            sample_keyframes(&self.translations, time, |a, b, k| {
+                a.lerp(b, k)
+            })
+        {
+            transform.translation = translation;
+        }
+
+        if let Some(rotation) =
+            sample_keyframes(&self.rotations, time, |a, b, k| {
+                a.slerp(b, k)
+            })
+        {
+            transform.rotation = rotation;
+        }
+
+        if let Some(scale) =
+            sample_keyframes(&self.scales, time, |a, b, k| a.lerp(b, k))
+        {
+            transform.scale = scale;
+        }
+    }
+
+    pub fn get_duration(&self) -> f32 {
+        [
+            self.translations.last().map(|k| k.time),
+            self.rotations.last().map(|k| k.time),
+            self.scales.last().map(|k| k.time),
+        ]
+        .iter()
+        .flatten()
+        .fold(0.0, |a, &b| a.max(b))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AnimationClip {
+    pub name: String,
+    pub duration: f32,
+    pub tracks: Vec<JointTrack>,
+}
+
+impl AnimationClip {
+    pub fn new(name: &str, tracks: Vec<JointTrack>) -> Self {
+        let duration =
+            tracks.iter().fold(0.0, |d, t| t.get_duration().max(d));
+
+        Self { name: name.to_string(), duration, tracks }
+    }
+
+    pub fn sample(
+        &self,
+        skeleton: &Skeleton,
+        time: f32,
+        is_looped: bool,
+    ) -> Pose {
+        let mut pose = skeleton.get_rest_pose();
+        self.sample_into(&mut pose, time, is_looped);
+
+        pose
+    }
+
+    pub fn sample_into(
+        &self,
+        pose: &mut Pose,
+        time: f32,
+        is_looped: bool,
+    ) {
+        let time = if is_looped && self.duration > 0.0 {
+            time.rem_euclid(self.duration)
+        } else {
+            time.clamp(0.0, self.duration)
+        };
+
+        for track in self.tracks.iter() {
+            track.sample(time, &mut pose.transforms[track.joint]);
+        }
+    }
+}
+
+fn sample_keyframes<T: Copy>(
+    keyframes: &[Keyframe<T>],
+    time: f32,
+    interpolate: impl Fn(&T, &T, f32) -> T,
+) -> Option<T> {
+    let first = keyframes.first()?;
+    let last = keyframes.last()?;
+    if time <= first.time {
+        return Some(first.value);
+    } else if time >= last.time {
+        return Some(last.value);
+    }
+
+    let idx = keyframes.partition_point(|k| k.time <= time);
+    let a = &keyframes[idx - 1];
+    let b = &keyframes[idx];
+    let k = (time - a.time) / (b.time - a.time);
+
+    Some(interpolate(&a.value, &b.value, k))
+}
@@ -0,0 +1,305 @@
+use crate::color::*;
+use crate::common::*;
+use crate::geometry::rotate;
+use crate::shapes::*;
+use crate::texture_atlas::AtlasRegion;
+use nalgebra::{point, Point2, Vector2};
+
+// Per axis, so the tiled center stays within a vertex buffer
+const MAX_N_SLICE_TILES: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Sprite {
+    pub tex: Texture,
+    // Texture region in pixels with the bottom-left origin
+    pub region: Rectangle,
+    // Size before scaling. Equals to the region size by default
+    pub size: Vector2<f32>,
+    // Sprite position and the sprite point which is placed there. The
+    // sprite rotates and scales around this point
+    pub pivot: Pivot,
+    // Counter-clockwise, in radians
+    pub rotation: f32,
+    pub scale: Vector2<f32>,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    pub tint: Color,
+}
+
+impl Sprite {
+    pub fn new(tex: Texture, region: Rectangle) -> Self {
+        Self {
+            tex,
+            region,
+            size: region.get_size(),
+            pivot: Pivot::center(Point2::origin()),
+            rotation: 0.0,
+            scale: Vector2::new(1.0, 1.0),
+            flip_x: false,
+            flip_y: false,
+            tint: WHITE,
+        }
+    }
+
+    pub fn from_texture(tex: Texture) -> Self {
+        let size = Vector2::new(tex.width as f32, tex.height as f32);
+
+        Self::new(tex, Rectangle::from_bot_left(Point2::origin(), size))
+    }
+
+    pub fn from_atlas_region(tex: Texture, region: &AtlasRegion) -> Self {
+        Self::new(tex, region.rect)
+    }
+
+    pub fn get_position(&self) -> Point2<f32> {
+        self.pivot.p
+    }
+
+    pub fn set_position(&mut self, position: Point2<f32>) {
+        self.pivot.p = position;
+    }
+
+    // World space corners: bot-left, bot-right, top-right, top-left of
+    // the unrotated sprite
+    pub fn get_corners(&self) -> [Point2<f32>; 4] {
+        let local = Pivot::new(self.pivot.ty, Point2::origin());
+        let rect = Rectangle::from_pivot(local, self.size);
+
+        rect.get_vertices().map(|corner| {
+            let corner = corner.coords.component_mul(&self.scale);
+            self.pivot.p + rotate(&corner, self.rotation)
+        })
+    }
+
+    // Normalized texture coordinates which match the `get_corners` order
+    pub fn get_texcoords(&self) -> [Point2<f32>; 4] {
+        let tex_size =
+            Vector2::new(self.tex.width as f32, self.tex.height as f32);
+        let min =
+            self.region.get_bot_left().coords.component_div(&tex_size);
+        let max =
+            self.region.get_top_right().coords.component_div(&tex_size);
+        let (x0, x1) = if self.flip_x {
+            (max.x, min.x)
+        } else {
+            (min.x, max.x)
+        };
+        let (y0, y1) = if self.flip_y {
+            (max.y, min.y)
+        } else {
+            (min.y, max.y)
+        };
+
+        [
+            point![x0, y0],
+            point![x1, y0],
+            point![x1, y1],
+            point![x0, y1],
+        ]
+    }
+
+    pub fn get_triangles(&self) -> [Triangle; 2] {
+        let [a, b, c, d] = self.get_corners();
+
+        [Triangle::new_2d(a, b, c), Triangle::new_2d(a, c, d)]
+    }
+
+    pub fn get_texcoord_triangles(&self) -> [Triangle; 2] {
+        let [a, b, c, d] = self.get_texcoords();
+
+        [Triangle::new_2d(a, b, c), Triangle::new_2d(a, c, d)]
+    }
+
+    pub fn get_bounding_rect(&self) -> Rectangle {
+        let corners = self.get_corners();
+        let (min, max) = corners
+            .iter()
+            .fold((corners[0], corners[0]), |(min, max), p| {
+                (min.inf(p), max.sup(p))
+            });
+
+        Rectangle::new(min, max)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SliceMode {
+    Stretch,
+    Tile,
+}
+
+// Scalable texture region: the corners keep their size, the edges and
+// the center are stretched or tiled to fill the rest
+#[derive(Debug, Clone, Copy)]
+pub struct NineSlice {
+    pub tex: Texture,
+    // Texture region in pixels with the bottom-left origin
+    pub region: Rectangle,
+    // Border sizes in texture pixels: left, right, bottom, top
+    pub insets: [f32; 4],
+    // Size of a texture pixel in the drawn borders and tiles
+    pub scale: f32,
+    pub edge_mode: SliceMode,
+    pub center_mode: SliceMode,
+    pub tint: Color,
+}
+
+impl NineSlice {
+    pub fn new(tex: Texture, region: Rectangle, insets: [f32; 4]) -> Self {
+        Self {
+            tex,
+            region,
+            insets,
+            scale: 1.0,
+            edge_mode: SliceMode::Stretch,
+            center_mode: SliceMode::Stretch,
+            tint: WHITE,
+        }
+    }
+
+    // Position and normalized texture coordinates of each patch which
+    // fills the rect. Borders shrink if the rect is too small for them
+    pub fn get_patches(
+        &self,
+        rect: Rectangle,
+    ) -> Vec<(Rectangle, Rectangle)> {
+        let [left, right, bot, top] = self.insets;
+        let cols = get_slice_spans(
+            (rect.get_min_x(), rect.get_max_x()),
+            (self.region.get_min_x(), self.region.get_max_x()),
+            (left, right),
+            self.scale,
+        );
+        let rows = get_slice_spans(
+            (rect.get_min_y(), rect.get_max_y()),
+            (self.region.get_min_y(), self.region.get_max_y()),
+            (bot, top),
+            self.scale,
+        );
+        let tex_size =
+            Vector2::new(self.tex.width as f32, self.tex.height as f32);
+
+        let mut patches = Vec::with_capacity(9);
+        for (row_idx, row) in rows.iter().enumerate() {
+            for (col_idx, col) in cols.iter().enumerate() {
+                let mode = match (col_idx == 1, row_idx == 1) {
+                    (true, true) => self.center_mode,
+                    (false, false) => SliceMode::Stretch,
+                    _ => self.edge_mode,
+                };
+                let is_tiled = mode == SliceMode::Tile;
+                let xs = get_slice_tiles(col, is_tiled && col_idx == 1);
+                let ys = get_slice_tiles(row, is_tiled && row_idx == 1);
+
+                for y in ys.iter() {
+                    for x in xs.iter() {
+                        if x.dst.1 <= x.dst.0 || y.dst.1 <= y.dst.0 {
+                            continue;
+                        }
+
+                        let position = Rectangle::new(
+                            point![x.dst.0, y.dst.0],
+                            point![x.dst.1, y.dst.1],
+                        );
+                        let texcoords = Rectangle::new(
+                            point![
+                                x.src.0 / tex_size.x,
+                                y.src.0 / tex_size.y
+                            ],
+                            point![
+                                x.src.1 / tex_size.x,
+                                y.src.1 / tex_size.y
+                            ],
+                        );
+                        patches.push((position, texcoords));
+                    }
+                }
+            }
+        }
+
+        patches
+    }
+}
+
+// Matching ranges along one axis: on the screen and in the texture
+#[derive(Debug, Clone, Copy)]
+struct SliceSpan {
+    dst: (f32, f32),
+    src: (f32, f32),
+    // Screen length of a single tile
+    tile_len: f32,
+}
+
+fn get_slice_spans(
+    dst: (f32, f32),
+    src: (f32, f32),
+    insets: (f32, f32),
+    scale: f32,
+) -> [SliceSpan; 3] {
+    let mut borders = (insets.0 * scale, insets.1 * scale);
+    let dst_len = dst.1 - dst.0;
+    if borders.0 + borders.1 > dst_len {
+        let k = dst_len / (borders.0 + borders.1);
+        borders = (borders.0 * k, borders.1 * k);
+    }
+
+    let src_mid = (src.0 + insets.0, src.1 - insets.1);
+    let dst_mid = (dst.0 + borders.0, dst.1 - borders.1);
+
+    [
+        SliceSpan {
+            dst: (dst.0, dst_mid.0),
+            src: (src.0, src_mid.0),
+            tile_len: 0.0,
+        },
+        SliceSpan {
+            dst: dst_mid,
+            src: src_mid,
+            tile_len: (src_mid.1 - src_mid.0) * scale,
+        },
+        SliceSpan {
+            dst: (dst_mid.1, dst.1),
+            src: (src_mid.1, src.1),
+            tile_len: 0.0,
+        },
+    ]
+}
+
+// Splits the span into whole tiles and the last cropped one. Spans with
+// too many tiles get fewer stretched ones instead
+fn get_slice_tiles(span: &SliceSpan, is_tiled: bool) -> Vec<SliceSpan> {
+    if !is_tiled || span.tile_len <= 0.0 {
+        return vec![*span];
+    }
+
+    let dst_len = span.dst.1 - span.dst.0;
+    let n_tiles = (dst_len / span.tile_len).ceil();
+    if n_tiles.is_nan() || n_tiles < 1.0 {
+        return vec![];
+    }
+
+    let (n_tiles, tile_len) = if n_tiles > MAX_N_SLICE_TILES as f32 {
+        (MAX_N_SLICE_TILES, dst_len / MAX_N_SLICE_TILES as f32)
+    } else {
+        (n_tiles as usize, span.tile_len)
+    };
+
+    let src_len = span.src.1 - span.src.0;
+    (0..n_tiles)
+        .map(|i| {
+            let start = span.dst.0 + tile_len * i as f32;
+            let end = if i + 1 == n_tiles {
+                span.dst.1
+            } else {
+                start + tile_len
+            };
+            let k = ((end - start) / tile_len).min(1.0);
+
+            SliceSpan {
+                dst: (start, end),
+                src: (span.src.0, span.src.0 + src_len * k),
+                tile_len,
+            }
+        })
+        .collect()
+}
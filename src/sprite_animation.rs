@@ -0,0 +1,396 @@
+use crate::shapes::Rectangle;
+use crate::texture_atlas::*;
+use nalgebra::{point, Vector2};
+use serde_json::Value;
+
+const DEFAULT_ANIMATION_NAME: &str = "default";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoopMode {
+    Once,
+    Loop,
+    PingPong,
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimationFrame {
+    // In pixels with the bottom-left origin (as `Sprite::region` expects)
+    pub region: Rectangle,
+    // Normalized texture coordinates (as `draw_rect` expects)
+    pub texcoords: Rectangle,
+    // In seconds
+    pub duration: f32,
+}
+
+impl AnimationFrame {
+    pub fn new(
+        region: Rectangle,
+        tex_size: Vector2<f32>,
+        duration: f32,
+    ) -> Self {
+        let min = region.get_bot_left().coords.component_div(&tex_size);
+        let max = region.get_top_right().coords.component_div(&tex_size);
+        let texcoords = Rectangle::new(min.into(), max.into());
+
+        Self { region, texcoords, duration }
+    }
+
+    pub fn from_atlas_region(region: &AtlasRegion, duration: f32) -> Self {
+        Self {
+            region: region.rect,
+            texcoords: region.texcoords,
+            duration,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimationEvent {
+    pub frame: usize,
+    pub name: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Animation {
+    pub name: String,
+    pub frames: Vec<AnimationFrame>,
+    pub loop_mode: LoopMode,
+    // Fired when the player enters the event frame
+    pub events: Vec<AnimationEvent>,
+}
+
+impl Animation {
+    pub fn new(
+        name: &str,
+        frames: Vec<AnimationFrame>,
+        loop_mode: LoopMode,
+    ) -> Self {
+        if frames.is_empty() {
+            panic!("Animation {} should have at least one frame", name);
+        }
+
+        Self {
+            name: name.to_string(),
+            frames,
+            loop_mode,
+            events: vec![],
+        }
+    }
+
+    // Frames of the uniform grid sprite sheet are indexed row by row
+    // starting from the top-left one
+    pub fn from_grid(
+        name: &str,
+        tex_size: Vector2<f32>,
+        frame_size: Vector2<f32>,
+        frame_ids: &[usize],
+        frame_duration: f32,
+        loop_mode: LoopMode,
+    ) -> Self {
+        let n_cols = (tex_size.x / frame_size.x).floor() as usize;
+        if n_cols == 0 {
+            panic!("Animation frame is wider than the texture");
+        }
+
+        let frames = frame_ids
+            .iter()
+            .map(|&idx| {
+                let col = (idx % n_cols) as f32;
+                let row = (idx / n_cols) as f32;
+                let bot_left = point![
+                    col * frame_size.x,
+                    tex_size.y - (row + 1.0) * frame_size.y
+                ];
+                let region =
+                    Rectangle::from_bot_left(bot_left, frame_size);
+
+                AnimationFrame::new(region, tex_size, frame_duration)
+            })
+            .collect();
+
+        Self::new(name, frames, loop_mode)
+    }
+
+    pub fn from_atlas(
+        name: &str,
+        atlas: &TextureAtlas,
+        region_names: &[&str],
+        frame_duration: f32,
+        loop_mode: LoopMode,
+    ) -> Self {
+        let frames = region_names
+            .iter()
+            .map(|region_name| {
+                let region =
+                    atlas.get_region(region_name).unwrap_or_else(|| {
+                        panic!("Atlas doesn't have {} region", region_name)
+                    });
+
+                AnimationFrame::from_atlas_region(region, frame_duration)
+            })
+            .collect();
+
+        Self::new(name, frames, loop_mode)
+    }
+
+    pub fn add_event(&mut self, frame: usize, name: &str) {
+        if frame >= self.frames.len() {
+            panic!(
+                "Animation {} has only {} frames, can't add event to {}",
+                self.name,
+                self.frames.len(),
+                frame
+            );
+        }
+
+        self.events
+            .push(AnimationEvent { frame, name: name.to_string() });
+    }
+
+    pub fn get_n_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    // Duration of a single pass through the frames
+    pub fn get_duration(&self) -> f32 {
+        self.frames.iter().map(|frame| frame.duration).sum()
+    }
+}
+
+// Loads animations from the Aseprite json export (both hash and array
+// frames layouts). Each frame tag becomes a separate animation, if there
+// are no tags, all frames are put into the single looped animation
+pub fn load_aseprite_animations(json: &str) -> Vec<Animation> {
+    let json: Value =
+        serde_json::from_str(json).expect("Can't parse Aseprite json");
+
+    let get_u32 = |value: &Value, key: &str| {
+        value[key].as_u64().unwrap_or_else(|| {
+            panic!("Aseprite json should have the `{}` field", key)
+        }) as u32
+    };
+
+    let meta_size = &json["meta"]["size"];
+    let tex_size = (get_u32(meta_size, "w"), get_u32(meta_size, "h"));
+    let frames: Vec<&Value> = match &json["frames"] {
+        Value::Array(frames) => frames.iter().collect(),
+        Value::Object(frames) => frames.values().collect(),
+        _ => panic!("Aseprite json should have the `frames` field"),
+    };
+
+    let frames: Vec<AnimationFrame> = frames
+        .iter()
+        .map(|frame| {
+            let rect = &frame["frame"];
+            let region = AtlasRegion::new(
+                "",
+                get_u32(rect, "x"),
+                get_u32(rect, "y"),
+                get_u32(rect, "w"),
+                get_u32(rect, "h"),
+                tex_size,
+            );
+            let duration = get_u32(frame, "duration") as f32 / 1000.0;
+
+            AnimationFrame::from_atlas_region(&region, duration)
+        })
+        .collect();
+
+    let tags = json["meta"]["frameTags"].as_array();
+    let Some(tags) = tags.filter(|tags| !tags.is_empty()) else {
+        return vec![Animation::new(
+            DEFAULT_ANIMATION_NAME,
+            frames,
+            LoopMode::Loop,
+        )];
+    };
+
+    tags.iter()
+        .map(|tag| {
+            let name = tag["name"]
+                .as_str()
+                .expect("Aseprite frame tag should have the `name` field");
+            let from = get_u32(tag, "from") as usize;
+            let to = get_u32(tag, "to") as usize;
+            if from > to || to >= frames.len() {
+                panic!("Aseprite frame tag {} has bad frames range", name);
+            }
+
+            let mut tag_frames = frames[from..=to].to_vec();
+            let direction = tag["direction"].as_str().unwrap_or("forward");
+            if direction.ends_with("reverse") {
+                tag_frames.reverse();
+            }
+
+            // Aseprite `repeat` is a number of passes, only the single
+            // pass is distinguished from the endless loop here
+            let loop_mode = if direction.starts_with("pingpong") {
+                LoopMode::PingPong
+            } else if tag["repeat"].as_str() == Some("1") {
+                LoopMode::Once
+            } else {
+                LoopMode::Loop
+            };
+
+            Animation::new(name, tag_frames, loop_mode)
+        })
+        .collect()
+}
+
+pub struct AnimationPlayer {
+    // Playback rate multiplier, should be non-negative
+    pub speed: f32,
+    pub is_paused: bool,
+
+    animations: Vec<Animation>,
+    animation_idx: usize,
+    frame_idx: usize,
+    frame_time: f32,
+    // Ping-pong direction: 1 (forward) or -1 (backward)
+    direction: i32,
+    is_finished: bool,
+    events: Vec<String>,
+}
+
+impl AnimationPlayer {
+    pub fn new(animations: Vec<Animation>) -> Self {
+        if animations.is_empty() {
+            panic!("Animation player should have at least one animation");
+        }
+
+        let mut player = Self {
+            speed: 1.0,
+            is_paused: false,
+            animations,
+            animation_idx: 0,
+            frame_idx: 0,
+            frame_time: 0.0,
+            direction: 1,
+            is_finished: false,
+            events: vec![],
+        };
+        player.restart();
+
+        player
+    }
+
+    pub fn add_animation(&mut self, animation: Animation) {
+        if self.find_animation(&animation.name).is_some() {
+            panic!("Player already has the {} animation", animation.name);
+        }
+
+        self.animations.push(animation);
+    }
+
+    pub fn find_animation(&self, name: &str) -> Option<usize> {
+        self.animations.iter().position(|a| a.name == name)
+    }
+
+    // Switches to the animation and plays it from the start. Does nothing
+    // if this animation is already playing
+    pub fn play(&mut self, name: &str) {
+        let idx = self.find_animation(name).unwrap_or_else(|| {
+            panic!("Player doesn't have the {} animation", name)
+        });
+
+        if idx != self.animation_idx || self.is_finished {
+            self.animation_idx = idx;
+            self.restart();
+        }
+    }
+
+    pub fn restart(&mut self) {
+        self.frame_idx = 0;
+        self.frame_time = 0.0;
+        self.direction = 1;
+        self.is_finished = false;
+        self.fire_events();
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        if self.is_paused
+            || self.is_finished
+            || self.get_animation().get_duration() <= 0.0
+        {
+            return;
+        }
+
+        self.frame_time += dt * self.speed;
+        while !self.is_finished
+            && self.frame_time >= self.get_frame().duration
+        {
+            self.frame_time -= self.get_frame().duration;
+            self.advance();
+        }
+    }
+
+    // Returns the events fired since the previous call
+    pub fn take_events(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.events)
+    }
+
+    pub fn get_animation(&self) -> &Animation {
+        &self.animations[self.animation_idx]
+    }
+
+    pub fn get_frame_idx(&self) -> usize {
+        self.frame_idx
+    }
+
+    pub fn get_frame(&self) -> &AnimationFrame {
+        &self.get_animation().frames[self.frame_idx]
+    }
+
+    pub fn get_texcoords(&self) -> Rectangle {
+        self.get_frame().texcoords
+    }
+
+    pub fn get_region(&self) -> Rectangle {
+        self.get_frame().region
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.is_finished
+    }
+
+    fn advance(&mut self) {
+        let animation = self.get_animation();
+        let n_frames = animation.get_n_frames() as i32;
+        let mut next = self.frame_idx as i32 + self.direction;
+
+        match animation.loop_mode {
+            LoopMode::Once => {
+                if next >= n_frames {
+                    self.frame_time = 0.0;
+                    self.is_finished = true;
+                    return;
+                }
+            }
+            LoopMode::Loop => {
+                next = next.rem_euclid(n_frames);
+            }
+            LoopMode::PingPong => {
+                if n_frames == 1 {
+                    next = 0;
+                } else if next < 0 || next >= n_frames {
+                    self.direction = -self.direction;
+                    next = self.frame_idx as i32 + self.direction;
+                }
+            }
+        }
+
+        self.frame_idx = next as usize;
+        self.fire_events();
+    }
+
+    fn fire_events(&mut self) {
+        let animation = &self.animations[self.animation_idx];
+        self.events.extend(
+            animation
+                .events
+                .iter()
+                .filter(|event| event.frame == self.frame_idx)
+                .map(|event| event.name.clone()),
+        );
+    }
+}
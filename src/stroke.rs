@@ -0,0 +1,301 @@
+use crate::geometry::*;
+use crate::shapes::*;
+use core::f32::consts::PI;
+use nalgebra::{Point2, Vector2};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineJoin {
+    Miter,
+    Bevel,
+    Round,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineCap {
+    Butt,
+    Square,
+    Round,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StrokeStyle {
+    pub thickness: f32,
+    pub join: LineJoin,
+    pub cap: LineCap,
+    // Max ratio of the miter length to the half thickness. Sharper
+    // joins fall back to bevel
+    pub miter_limit: f32,
+    // Alternating dash and gap lengths, empty for a solid line
+    pub dashes: Vec<f32>,
+    pub dash_offset: f32,
+}
+
+impl StrokeStyle {
+    pub fn new(thickness: f32) -> Self {
+        Self {
+            thickness,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            miter_limit: 4.0,
+            dashes: vec![],
+            dash_offset: 0.0,
+        }
+    }
+
+    pub fn new_dashed(thickness: f32, dashes: &[f32]) -> Self {
+        let mut style = Self::new(thickness);
+        style.dashes = dashes.to_vec();
+
+        style
+    }
+}
+
+pub fn stroke_line(line: &Line, style: &StrokeStyle) -> Vec<Triangle> {
+    stroke_polyline(&[line.s, line.e], false, style)
+}
+
+pub fn stroke_polyline(
+    points: &[Point2<f32>],
+    is_closed: bool,
+    style: &StrokeStyle,
+) -> Vec<Triangle> {
+    let mut points = points.to_vec();
+    points.dedup_by(|a, b| (*a - *b).norm() <= f32::EPSILON);
+    if is_closed
+        && points.len() > 1
+        && (points[0] - points[points.len() - 1]).norm() <= f32::EPSILON
+    {
+        points.pop();
+    }
+
+    let mut triangles = Vec::with_capacity(points.len() * 6);
+    if points.len() < 2 || style.thickness <= 0.0 {
+        return triangles;
+    }
+
+    let dashes = get_dash_pattern(&style.dashes);
+    if dashes.is_empty() {
+        stroke_solid(&points, is_closed, style, &mut triangles);
+    } else {
+        if is_closed {
+            points.push(points[0]);
+        }
+        for dash in split_into_dashes(&points, &dashes, style.dash_offset)
+        {
+            stroke_solid(&dash, false, style, &mut triangles);
+        }
+    }
+
+    triangles
+}
+
+fn stroke_solid(
+    points: &[Point2<f32>],
+    is_closed: bool,
+    style: &StrokeStyle,
+    triangles: &mut Vec<Triangle>,
+) {
+    let hw = 0.5 * style.thickness;
+    let n_points = points.len();
+    let n_segments = if is_closed { n_points } else { n_points - 1 };
+    let get_dir =
+        |i: usize| (points[(i + 1) % n_points] - points[i]).normalize();
+
+    for i in 0..n_segments {
+        let dir = get_dir(i);
+        let mut s = points[i];
+        let mut e = points[(i + 1) % n_points];
+
+        if !is_closed && style.cap == LineCap::Square {
+            if i == 0 {
+                s -= dir * hw;
+            }
+            if i == n_segments - 1 {
+                e += dir * hw;
+            }
+        }
+
+        push_quad(s, e, rotate90(&dir) * hw, triangles);
+    }
+
+    let joints = if is_closed {
+        0..n_points
+    } else {
+        1..n_points - 1
+    };
+    for i in joints {
+        let prev_dir = get_dir((i + n_points - 1) % n_points);
+        let next_dir = get_dir(i);
+        push_join(points[i], prev_dir, next_dir, style, triangles);
+    }
+
+    if !is_closed && style.cap == LineCap::Round {
+        let s = points[0];
+        let e = points[n_points - 1];
+        let s_normal = rotate90(&get_dir(0)) * hw;
+        let e_normal = rotate90(&get_dir(n_points - 2)) * hw;
+
+        push_fan(s, s_normal, PI, triangles);
+        push_fan(e, -e_normal, PI, triangles);
+    }
+}
+
+fn push_quad(
+    s: Point2<f32>,
+    e: Point2<f32>,
+    offset: Vector2<f32>,
+    triangles: &mut Vec<Triangle>,
+) {
+    triangles.push(Triangle::new_2d(s - offset, e - offset, e + offset));
+    triangles.push(Triangle::new_2d(s - offset, e + offset, s + offset));
+}
+
+fn push_join(
+    point: Point2<f32>,
+    prev_dir: Vector2<f32>,
+    next_dir: Vector2<f32>,
+    style: &StrokeStyle,
+    triangles: &mut Vec<Triangle>,
+) {
+    let hw = 0.5 * style.thickness;
+    let cross = prev_dir.perp(&next_dir);
+    let dot = prev_dir.dot(&next_dir);
+    let angle = cross.atan2(dot);
+    if angle.abs() <= 1e-4 {
+        return;
+    }
+
+    // The inner side is covered by the overlapping segment quads, so only
+    // the gap on the outer side of the turn is filled
+    let side = if cross > 0.0 { -1.0 } else { 1.0 };
+    let prev_offset = rotate90(&prev_dir) * hw * side;
+    let next_offset = rotate90(&next_dir) * hw * side;
+    let a = point + prev_offset;
+    let b = point + next_offset;
+
+    match style.join {
+        LineJoin::Round => push_fan(point, prev_offset, angle, triangles),
+        LineJoin::Miter => {
+            let miter = (prev_offset + next_offset)
+                .try_normalize(f32::EPSILON)
+                .map(|dir| (dir, hw / dir.dot(&prev_offset) * hw))
+                .filter(|(_, len)| *len / hw <= style.miter_limit);
+
+            if let Some((miter_dir, miter_len)) = miter {
+                let tip = point + miter_dir * miter_len;
+                push_ccw_triangle(point, a, tip, triangles);
+                push_ccw_triangle(point, tip, b, triangles);
+            } else {
+                push_ccw_triangle(point, a, b, triangles);
+            }
+        }
+        LineJoin::Bevel => push_ccw_triangle(point, a, b, triangles),
+    }
+}
+
+// Triangle fan around the center, starting at the offset and sweeping the
+// given (signed) angle
+fn push_fan(
+    center: Point2<f32>,
+    offset: Vector2<f32>,
+    angle: f32,
+    triangles: &mut Vec<Triangle>,
+) {
+    let n_segments = get_n_arc_segments(offset.norm(), angle.abs());
+    let step = angle / n_segments as f32;
+
+    let mut prev = center + offset;
+    for i in 1..=n_segments {
+        let curr = center + rotate(&offset, step * i as f32);
+        push_ccw_triangle(center, prev, curr, triangles);
+        prev = curr;
+    }
+}
+
+// Right turns sweep clockwise, so their triangles are flipped to not be
+// culled as the back faces
+fn push_ccw_triangle(
+    a: Point2<f32>,
+    b: Point2<f32>,
+    c: Point2<f32>,
+    triangles: &mut Vec<Triangle>,
+) {
+    if (b - a).perp(&(c - a)) < 0.0 {
+        triangles.push(Triangle::new_2d(a, c, b));
+    } else {
+        triangles.push(Triangle::new_2d(a, b, c));
+    }
+}
+
+// Odd patterns are repeated twice, so the dashes and gaps alternate
+fn get_dash_pattern(dashes: &[f32]) -> Vec<f32> {
+    if dashes.iter().sum::<f32>() <= 0.0 || dashes.iter().any(|&d| d < 0.0)
+    {
+        return vec![];
+    }
+
+    if dashes.len() % 2 == 1 {
+        [dashes, dashes].concat()
+    } else {
+        dashes.to_vec()
+    }
+}
+
+fn split_into_dashes(
+    points: &[Point2<f32>],
+    dashes: &[f32],
+    offset: f32,
+) -> Vec<Vec<Point2<f32>>> {
+    let pattern_len: f32 = dashes.iter().sum();
+    let mut offset = offset.rem_euclid(pattern_len);
+    let mut dash_idx = 0;
+    while offset >= dashes[dash_idx] {
+        offset -= dashes[dash_idx];
+        dash_idx = (dash_idx + 1) % dashes.len();
+    }
+
+    let mut result = vec![];
+    let mut curr_dash = vec![];
+    let mut dash_left = dashes[dash_idx] - offset;
+    let mut is_on = dash_idx % 2 == 0;
+    if is_on {
+        curr_dash.push(points[0]);
+    }
+
+    for segment in points.windows(2) {
+        let (mut s, e) = (segment[0], segment[1]);
+        let mut segment_left = (e - s).norm();
+        let dir = (e - s) / segment_left;
+
+        while dash_left < segment_left {
+            s += dir * dash_left;
+            segment_left -= dash_left;
+
+            curr_dash.push(s);
+            if is_on {
+                result.push(std::mem::take(&mut curr_dash));
+            }
+
+            is_on = !is_on;
+            dash_idx = (dash_idx + 1) % dashes.len();
+            dash_left = dashes[dash_idx];
+        }
+
+        dash_left -= segment_left;
+        if is_on {
+            curr_dash.push(e);
+        }
+    }
+
+    if is_on {
+        result.push(curr_dash);
+    }
+
+    // Zero length dashes have no direction, so they are skipped
+    for dash in result.iter_mut() {
+        dash.dedup_by(|a, b| (*a - *b).norm() <= f32::EPSILON);
+    }
+    result.retain(|dash| dash.len() > 1);
+
+    result
+}
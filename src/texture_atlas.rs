@@ -0,0 +1,399 @@
+use crate::shapes::Rectangle;
+use image::{
+    imageops, load_from_memory, load_from_memory_with_format, ImageFormat,
+    RgbaImage,
+};
+use nalgebra::{point, Vector2};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::Path;
+
+const MIN_ATLAS_SIZE: u32 = 64;
+
+#[derive(Debug, Clone)]
+pub struct AtlasRegion {
+    pub name: String,
+    // In pixels with the bottom-left origin (as `Sprite::region` expects)
+    pub rect: Rectangle,
+    // Normalized texture coordinates
+    pub texcoords: Rectangle,
+}
+
+impl AtlasRegion {
+    // Region position is given in the image pixels (top-left origin)
+    pub fn new(
+        name: &str,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        atlas_size: (u32, u32),
+    ) -> Self {
+        let (atlas_width, atlas_height) =
+            (atlas_size.0 as f32, atlas_size.1 as f32);
+        let bot_left =
+            point![x as f32, atlas_height - (y + height) as f32];
+        let size = Vector2::new(width as f32, height as f32);
+        let rect = Rectangle::from_bot_left(bot_left, size);
+        let texcoords = Rectangle::new(
+            point![bot_left.x / atlas_width, bot_left.y / atlas_height],
+            point![
+                rect.get_max_x() / atlas_width,
+                rect.get_max_y() / atlas_height
+            ],
+        );
+
+        Self { name: name.to_string(), rect, texcoords }
+    }
+
+    // Top-left corner in the image pixels
+    fn get_image_position(&self, atlas_height: u32) -> (u32, u32) {
+        (
+            self.rect.get_min_x() as u32,
+            atlas_height - self.rect.get_max_y() as u32,
+        )
+    }
+}
+
+// Skyline bottom-left packer: the packed area is described by the
+// horizontal segments of its upper boundary, new rectangles are placed
+// as high (in the image coordinates) as possible
+#[derive(Debug, Clone)]
+pub struct SkylinePacker {
+    width: u32,
+    height: u32,
+    skyline: Vec<SkylineSegment>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SkylineSegment {
+    x: u32,
+    y: u32,
+    width: u32,
+}
+
+impl SkylinePacker {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            skyline: vec![SkylineSegment { x: 0, y: 0, width }],
+        }
+    }
+
+    pub fn get_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    // Returns the top-left corner (in the image coordinates) of the
+    // packed rectangle or None if it doesn't fit
+    pub fn pack(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let mut best: Option<(usize, u32, u32)> = None;
+
+        for idx in 0..self.skyline.len() {
+            let Some(y) = self.get_fit_y(idx, width, height) else {
+                continue;
+            };
+
+            let segment = self.skyline[idx];
+            let is_better = best.is_none_or(|(best_idx, best_y, _)| {
+                y < best_y
+                    || (y == best_y
+                        && segment.width < self.skyline[best_idx].width)
+            });
+            if is_better {
+                best = Some((idx, y, segment.x));
+            }
+        }
+
+        let (idx, y, x) = best?;
+        self.add_segment(idx, x, y + height, width);
+
+        Some((x, y))
+    }
+
+    fn get_fit_y(
+        &self,
+        idx: usize,
+        width: u32,
+        height: u32,
+    ) -> Option<u32> {
+        let x = self.skyline[idx].x;
+        if x + width > self.width {
+            return None;
+        }
+
+        let mut y = 0;
+        let mut width_left = width as i64;
+        let mut i = idx;
+        while width_left > 0 {
+            let segment = self.skyline.get(i)?;
+            y = y.max(segment.y);
+            if y + height > self.height {
+                return None;
+            }
+
+            width_left -= segment.width as i64;
+            i += 1;
+        }
+
+        Some(y)
+    }
+
+    fn add_segment(&mut self, idx: usize, x: u32, y: u32, width: u32) {
+        self.skyline.insert(idx, SkylineSegment { x, y, width });
+
+        // Shrink or remove the segments covered by the new one
+        let right = x + width;
+        let i = idx + 1;
+        while i < self.skyline.len() {
+            let segment = &mut self.skyline[i];
+            if segment.x >= right {
+                break;
+            }
+
+            let overlap = right - segment.x;
+            if overlap < segment.width {
+                segment.x += overlap;
+                segment.width -= overlap;
+                break;
+            }
+
+            self.skyline.remove(i);
+        }
+
+        // Merge neighbours of the same height
+        let mut i = 0;
+        while i + 1 < self.skyline.len() {
+            if self.skyline[i].y == self.skyline[i + 1].y {
+                self.skyline[i].width += self.skyline[i + 1].width;
+                self.skyline.remove(i + 1);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+pub struct TextureAtlasBuilder {
+    pub max_size: u32,
+    // Empty pixels around each image which prevent the filtering bleeding
+    pub padding: u32,
+
+    images: Vec<(String, RgbaImage)>,
+}
+
+impl TextureAtlasBuilder {
+    pub fn new(max_size: u32, padding: u32) -> Self {
+        Self { max_size, padding, images: vec![] }
+    }
+
+    pub fn add_image(&mut self, name: &str, image: RgbaImage) {
+        if self.images.iter().any(|(n, _)| n == name) {
+            panic!("Atlas already has the image named {}", name);
+        }
+
+        self.images.push((name.to_string(), image));
+    }
+
+    pub fn add_image_bytes(
+        &mut self,
+        name: &str,
+        bytes: &[u8],
+        format: ImageFormat,
+    ) {
+        let image = load_from_memory_with_format(bytes, format)
+            .expect("Can't decode image bytes")
+            .into_rgba8();
+
+        self.add_image(name, image);
+    }
+
+    // Packs the images into the smallest power of two atlas which fits
+    // them all
+    pub fn build(&self) -> TextureAtlas {
+        // Larger images first: it packs much tighter
+        let mut order: Vec<usize> = (0..self.images.len()).collect();
+        order.sort_by_key(|&i| {
+            let image = &self.images[i].1;
+            std::cmp::Reverse((image.height(), image.width()))
+        });
+
+        let mut width = MIN_ATLAS_SIZE.min(self.max_size);
+        let mut height = width;
+        loop {
+            if let Some(positions) = self.try_pack(&order, width, height) {
+                return self.create_atlas(&positions, width, height);
+            }
+
+            if width == self.max_size && height == self.max_size {
+                panic!(
+                    "Can't pack {} images into the {}x{} atlas",
+                    self.images.len(),
+                    self.max_size,
+                    self.max_size
+                );
+            } else if width <= height {
+                width = (width * 2).min(self.max_size);
+            } else {
+                height = (height * 2).min(self.max_size);
+            }
+        }
+    }
+
+    fn try_pack(
+        &self,
+        order: &[usize],
+        width: u32,
+        height: u32,
+    ) -> Option<Vec<(u32, u32)>> {
+        let mut packer = SkylinePacker::new(width, height);
+        let mut positions = vec![(0, 0); self.images.len()];
+
+        for &i in order {
+            let image = &self.images[i].1;
+            let (x, y) = packer.pack(
+                image.width() + 2 * self.padding,
+                image.height() + 2 * self.padding,
+            )?;
+            positions[i] = (x + self.padding, y + self.padding);
+        }
+
+        Some(positions)
+    }
+
+    fn create_atlas(
+        &self,
+        positions: &[(u32, u32)],
+        width: u32,
+        height: u32,
+    ) -> TextureAtlas {
+        let mut image = RgbaImage::new(width, height);
+        let mut regions = Vec::with_capacity(self.images.len());
+
+        for ((name, sub_image), &(x, y)) in
+            self.images.iter().zip(positions)
+        {
+            imageops::replace(&mut image, sub_image, x as i64, y as i64);
+            regions.push(AtlasRegion::new(
+                name,
+                x,
+                y,
+                sub_image.width(),
+                sub_image.height(),
+                (width, height),
+            ));
+        }
+
+        TextureAtlas::new(image, regions)
+    }
+}
+
+pub struct TextureAtlas {
+    pub image: RgbaImage,
+
+    regions: Vec<AtlasRegion>,
+    region_ids: HashMap<String, usize>,
+}
+
+impl TextureAtlas {
+    pub fn new(image: RgbaImage, regions: Vec<AtlasRegion>) -> Self {
+        let region_ids = regions
+            .iter()
+            .enumerate()
+            .map(|(idx, region)| (region.name.clone(), idx))
+            .collect();
+
+        Self { image, regions, region_ids }
+    }
+
+    // Loads the atlas written by `save` (or the atlas packing tool)
+    pub fn from_bytes(image_bytes: &[u8], metadata: &str) -> Self {
+        let image = load_from_memory(image_bytes)
+            .expect("Can't decode atlas image bytes")
+            .into_rgba8();
+        let metadata: Value = serde_json::from_str(metadata)
+            .expect("Can't parse atlas metadata");
+
+        let size = (image.width(), image.height());
+        let get_u32 = |value: &Value, key: &str| {
+            value[key].as_u64().unwrap_or_else(|| {
+                panic!("Atlas region should have the `{}` field", key)
+            }) as u32
+        };
+        let regions = metadata["regions"]
+            .as_array()
+            .expect("Atlas metadata should have the `regions` array")
+            .iter()
+            .map(|region| {
+                AtlasRegion::new(
+                    region["name"].as_str().expect(
+                        "Atlas region should have the `name` field",
+                    ),
+                    get_u32(region, "x"),
+                    get_u32(region, "y"),
+                    get_u32(region, "width"),
+                    get_u32(region, "height"),
+                    size,
+                )
+            })
+            .collect();
+
+        Self::new(image, regions)
+    }
+
+    pub fn get_size(&self) -> (u32, u32) {
+        (self.image.width(), self.image.height())
+    }
+
+    pub fn get_regions(&self) -> &[AtlasRegion] {
+        &self.regions
+    }
+
+    pub fn get_region(&self, name: &str) -> Option<&AtlasRegion> {
+        self.region_ids.get(name).map(|&idx| &self.regions[idx])
+    }
+
+    pub fn get_texcoords(&self, name: &str) -> Option<Rectangle> {
+        self.get_region(name).map(|region| region.texcoords)
+    }
+
+    // Region positions are written in the image pixels (top-left origin)
+    pub fn to_metadata(&self) -> String {
+        let (width, height) = self.get_size();
+        let regions: Vec<Value> = self
+            .regions
+            .iter()
+            .map(|region| {
+                let (x, y) = region.get_image_position(height);
+                json!({
+                    "name": region.name,
+                    "x": x,
+                    "y": y,
+                    "width": region.rect.get_width() as u32,
+                    "height": region.rect.get_height() as u32,
+                })
+            })
+            .collect();
+
+        let metadata = json!({
+            "width": width,
+            "height": height,
+            "regions": regions,
+        });
+
+        serde_json::to_string_pretty(&metadata).unwrap()
+    }
+
+    pub fn save(
+        &self,
+        image_path: impl AsRef<Path>,
+        metadata_path: impl AsRef<Path>,
+    ) -> Result<(), String> {
+        self.image
+            .save_with_format(image_path, ImageFormat::Png)
+            .map_err(|e| format!("Can't save atlas image: {}", e))?;
+        std::fs::write(metadata_path, self.to_metadata())
+            .map_err(|e| format!("Can't save atlas metadata: {}", e))
+    }
+}
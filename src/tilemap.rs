@@ -0,0 +1,551 @@
+use crate::color::*;
+use crate::common::*;
+use crate::shapes::*;
+use crate::vertex_buffer::*;
+use nalgebra::{point, Point2, Vector2};
+use std::collections::HashMap;
+
+// Side of the square chunk in tiles
+pub const TILEMAP_CHUNK_SIZE: u32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Tile {
+    // Index in the layer tileset, None for the empty cell
+    pub id: Option<u32>,
+    // Number of counter-clockwise quarter turns, applied before flipping
+    pub rotation: u8,
+    pub flip_x: bool,
+    pub flip_y: bool,
+}
+
+impl Tile {
+    pub fn new(id: u32) -> Self {
+        Self { id: Some(id), ..Default::default() }
+    }
+
+    pub fn empty() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.id.is_none()
+    }
+
+    // Texture coordinates for the tile quad corners: bot-left, bot-right,
+    // top-right, top-left
+    pub fn get_texcoords(&self, texcoords: Rectangle) -> [Point2<f32>; 4] {
+        let (min, max) =
+            (texcoords.get_bot_left(), texcoords.get_top_right());
+        let mut corners = [
+            point![min.x, min.y],
+            point![max.x, min.y],
+            point![max.x, max.y],
+            point![min.x, max.y],
+        ];
+
+        // Rotating the image counter-clockwise moves each texture corner
+        // to the next quad corner
+        corners.rotate_right((self.rotation % 4) as usize);
+        if self.flip_x {
+            corners = [corners[1], corners[0], corners[3], corners[2]];
+        }
+        if self.flip_y {
+            corners = [corners[3], corners[2], corners[1], corners[0]];
+        }
+
+        corners
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Tileset {
+    pub tex: Texture,
+    // In texture pixels
+    pub tile_width: u32,
+    pub tile_height: u32,
+    // Pixels around the tiles grid and between the tiles
+    pub margin: u32,
+    pub spacing: u32,
+}
+
+impl Tileset {
+    pub fn new(tex: Texture, tile_width: u32, tile_height: u32) -> Self {
+        Self {
+            tex,
+            tile_width,
+            tile_height,
+            margin: 0,
+            spacing: 0,
+        }
+    }
+
+    pub fn get_n_cols(&self) -> u32 {
+        let width = self.tex.width.saturating_sub(2 * self.margin);
+
+        (width + self.spacing) / (self.tile_width + self.spacing)
+    }
+
+    pub fn get_n_rows(&self) -> u32 {
+        let height = self.tex.height.saturating_sub(2 * self.margin);
+
+        (height + self.spacing) / (self.tile_height + self.spacing)
+    }
+
+    pub fn get_n_tiles(&self) -> u32 {
+        self.get_n_cols() * self.get_n_rows()
+    }
+
+    // Tiles are indexed row by row starting from the top-left one.
+    // Returns the region in pixels with the bottom-left origin
+    pub fn get_tile_region(&self, id: u32) -> Rectangle {
+        let n_cols = self.get_n_cols();
+        if n_cols == 0 || id >= self.get_n_tiles() {
+            panic!("Tileset doesn't have tile {}", id);
+        }
+
+        let col = id % n_cols;
+        let row = id / n_cols;
+        let x = self.margin + col * (self.tile_width + self.spacing);
+        let top = self.margin + row * (self.tile_height + self.spacing);
+        let y = self.tex.height - top - self.tile_height;
+        let size =
+            Vector2::new(self.tile_width as f32, self.tile_height as f32);
+
+        Rectangle::from_bot_left(point![x as f32, y as f32], size)
+    }
+
+    pub fn get_tile_texcoords(&self, id: u32) -> Rectangle {
+        let region = self.get_tile_region(id);
+        let tex_size =
+            Vector2::new(self.tex.width as f32, self.tex.height as f32);
+        let min = region.get_bot_left().coords.component_div(&tex_size);
+        let max = region.get_top_right().coords.component_div(&tex_size);
+
+        Rectangle::new(min.into(), max.into())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TileAnimation {
+    pub tileset_idx: usize,
+    // Tile which is replaced by the animation frames
+    pub tile_id: u32,
+    // Tile ids with their durations in seconds
+    pub frames: Vec<(u32, f32)>,
+}
+
+impl TileAnimation {
+    pub fn get_duration(&self) -> f32 {
+        self.frames.iter().map(|(_, duration)| duration).sum()
+    }
+
+    pub fn get_frame_idx(&self, time: f32) -> usize {
+        let duration = self.get_duration();
+        if duration <= 0.0 {
+            return 0;
+        }
+
+        let mut time = time.rem_euclid(duration);
+        for (idx, (_, frame_duration)) in self.frames.iter().enumerate() {
+            if time < *frame_duration {
+                return idx;
+            }
+            time -= frame_duration;
+        }
+
+        self.frames.len() - 1
+    }
+
+    pub fn get_tile_id(&self, time: f32) -> u32 {
+        self.frames[self.get_frame_idx(time)].0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TileLayer {
+    pub name: String,
+    pub tileset_idx: usize,
+    pub is_visible: bool,
+
+    tiles: Vec<Tile>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct TilemapChunk {
+    vb_idx: Option<VertexBufferIdx>,
+    n_tiles: usize,
+    has_animations: bool,
+    is_dirty: bool,
+}
+
+// Grid of tiles with the bottom-left tile at (0, 0). Each layer is drawn
+// by the cached per-chunk vertex buffers which are rebuilt only after
+// their tiles change
+pub struct Tilemap {
+    // World position of the bottom-left corner
+    pub position: Point2<f32>,
+    // Tile size in world units
+    pub tile_size: Vector2<f32>,
+
+    width: u32,
+    height: u32,
+    tilesets: Vec<Tileset>,
+    layers: Vec<TileLayer>,
+    // Per layer, row by row starting from the bottom
+    chunks: Vec<Vec<TilemapChunk>>,
+    tile_animations: Vec<TileAnimation>,
+    // (tileset_idx, tile_id) -> animation idx
+    tile_animation_ids: HashMap<(usize, u32), usize>,
+    animation_time: f32,
+}
+
+impl Tilemap {
+    pub fn new(width: u32, height: u32, tile_size: Vector2<f32>) -> Self {
+        Self {
+            position: Point2::origin(),
+            tile_size,
+            width,
+            height,
+            tilesets: vec![],
+            layers: vec![],
+            chunks: vec![],
+            tile_animations: vec![],
+            tile_animation_ids: HashMap::new(),
+            animation_time: 0.0,
+        }
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn add_tileset(&mut self, tileset: Tileset) -> usize {
+        self.tilesets.push(tileset);
+
+        self.tilesets.len() - 1
+    }
+
+    pub fn get_tileset(&self, idx: usize) -> &Tileset {
+        &self.tilesets[idx]
+    }
+
+    pub fn get_tilesets(&self) -> &[Tileset] {
+        &self.tilesets
+    }
+
+    // Layers are drawn in the order they are added
+    pub fn add_layer(&mut self, name: &str, tileset_idx: usize) -> usize {
+        if tileset_idx >= self.tilesets.len() {
+            panic!("Tilemap doesn't have tileset {}", tileset_idx);
+        }
+
+        let n_tiles = (self.width * self.height) as usize;
+        self.layers.push(TileLayer {
+            name: name.to_string(),
+            tileset_idx,
+            is_visible: true,
+            tiles: vec![Tile::empty(); n_tiles],
+        });
+
+        let (n_cols, n_rows) = self.get_n_chunks();
+        let chunk = TilemapChunk { is_dirty: true, ..Default::default() };
+        self.chunks.push(vec![chunk; (n_cols * n_rows) as usize]);
+
+        self.layers.len() - 1
+    }
+
+    pub fn get_layers(&self) -> &[TileLayer] {
+        &self.layers
+    }
+
+    pub fn get_layer(&self, idx: usize) -> &TileLayer {
+        &self.layers[idx]
+    }
+
+    pub fn find_layer(&self, name: &str) -> Option<usize> {
+        self.layers.iter().position(|layer| layer.name == name)
+    }
+
+    pub fn set_layer_visible(
+        &mut self,
+        layer_idx: usize,
+        is_visible: bool,
+    ) {
+        self.layers[layer_idx].is_visible = is_visible;
+    }
+
+    pub fn add_tile_animation(&mut self, animation: TileAnimation) {
+        if animation.frames.is_empty() {
+            panic!("Tile animation should have at least one frame");
+        }
+
+        let key = (animation.tileset_idx, animation.tile_id);
+        self.tile_animation_ids
+            .insert(key, self.tile_animations.len());
+        self.tile_animations.push(animation);
+
+        for (layer_idx, layer) in self.layers.iter().enumerate() {
+            if layer.tileset_idx == key.0 {
+                self.chunks[layer_idx]
+                    .iter_mut()
+                    .for_each(|chunk| chunk.is_dirty = true);
+            }
+        }
+    }
+
+    pub fn get_tile_animations(&self) -> &[TileAnimation] {
+        &self.tile_animations
+    }
+
+    // Advances the animated tiles, chunks which contain them are rebuilt
+    // only when some animation switches its frame
+    pub fn update(&mut self, dt: f32) {
+        let prev_time = self.animation_time;
+        self.animation_time += dt;
+
+        let mut is_tileset_changed = vec![false; self.tilesets.len()];
+        for animation in self.tile_animations.iter() {
+            if animation.get_frame_idx(prev_time)
+                != animation.get_frame_idx(self.animation_time)
+            {
+                is_tileset_changed[animation.tileset_idx] = true;
+            }
+        }
+
+        for (layer_idx, layer) in self.layers.iter().enumerate() {
+            if !is_tileset_changed[layer.tileset_idx] {
+                continue;
+            }
+
+            for chunk in self.chunks[layer_idx].iter_mut() {
+                chunk.is_dirty |= chunk.has_animations;
+            }
+        }
+    }
+
+    pub fn get_tile(&self, layer_idx: usize, x: u32, y: u32) -> Tile {
+        self.layers[layer_idx].tiles[self.get_tile_idx(x, y)]
+    }
+
+    pub fn set_tile(
+        &mut self,
+        layer_idx: usize,
+        x: u32,
+        y: u32,
+        tile: Tile,
+    ) {
+        let tile_idx = self.get_tile_idx(x, y);
+        let layer = &mut self.layers[layer_idx];
+        if let Some(id) = tile.id {
+            let n_tiles = self.tilesets[layer.tileset_idx].get_n_tiles();
+            if id >= n_tiles {
+                panic!("Tileset has only {} tiles, got {}", n_tiles, id);
+            }
+        }
+
+        if layer.tiles[tile_idx] != tile {
+            layer.tiles[tile_idx] = tile;
+            let chunk_idx = self.get_chunk_idx(x, y);
+            self.chunks[layer_idx][chunk_idx].is_dirty = true;
+        }
+    }
+
+    pub fn get_tile_rect(&self, x: u32, y: u32) -> Rectangle {
+        let bot_left = self.position
+            + Vector2::new(x as f32, y as f32)
+                .component_mul(&self.tile_size);
+
+        Rectangle::from_bot_left(bot_left, self.tile_size)
+    }
+
+    pub fn world_to_tile(&self, p: Point2<f32>) -> Option<(u32, u32)> {
+        let p = (p - self.position).component_div(&self.tile_size);
+        if p.x < 0.0 || p.y < 0.0 {
+            return None;
+        }
+
+        let (x, y) = (p.x as u32, p.y as u32);
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        Some((x, y))
+    }
+
+    pub fn get_bounding_rect(&self) -> Rectangle {
+        let size = Vector2::new(self.width as f32, self.height as f32)
+            .component_mul(&self.tile_size);
+
+        Rectangle::from_bot_left(self.position, size)
+    }
+
+    pub fn get_n_chunks(&self) -> (u32, u32) {
+        (
+            self.width.div_ceil(TILEMAP_CHUNK_SIZE),
+            self.height.div_ceil(TILEMAP_CHUNK_SIZE),
+        )
+    }
+
+    // Chunk column and row ranges which overlap the world rect
+    pub fn get_visible_chunks(
+        &self,
+        rect: Rectangle,
+    ) -> (std::ops::Range<u32>, std::ops::Range<u32>) {
+        let (n_cols, n_rows) = self.get_n_chunks();
+        let chunk_size = self.tile_size * TILEMAP_CHUNK_SIZE as f32;
+        let min = (rect.get_bot_left() - self.position)
+            .component_div(&chunk_size);
+        let max = (rect.get_top_right() - self.position)
+            .component_div(&chunk_size);
+
+        let get_range = |min: f32, max: f32, n: u32| {
+            let from = min.floor().clamp(0.0, n as f32) as u32;
+            let to = max.ceil().clamp(0.0, n as f32) as u32;
+
+            from..to
+        };
+
+        (
+            get_range(min.x, max.x, n_cols),
+            get_range(min.y, max.y, n_rows),
+        )
+    }
+
+    // Returns the vertex buffer with the chunk tiles if the chunk was
+    // changed since the previous call, so it can be uploaded again
+    pub fn rebuild_chunk(
+        &mut self,
+        layer_idx: usize,
+        col: u32,
+        row: u32,
+    ) -> Option<VertexBufferCPU> {
+        let (n_cols, _) = self.get_n_chunks();
+        let chunk_idx = (row * n_cols + col) as usize;
+        if !self.chunks[layer_idx][chunk_idx].is_dirty {
+            return None;
+        }
+
+        let layer = &self.layers[layer_idx];
+        let tileset = &self.tilesets[layer.tileset_idx];
+        // Two triangles per tile
+        let n_vertices = TILEMAP_CHUNK_SIZE * TILEMAP_CHUNK_SIZE * 6;
+        let mut vb = VertexBufferCPU::with_capacity(n_vertices as usize);
+        let mut n_tiles = 0;
+        let mut has_animations = false;
+
+        let x_max = ((col + 1) * TILEMAP_CHUNK_SIZE).min(self.width);
+        let y_max = ((row + 1) * TILEMAP_CHUNK_SIZE).min(self.height);
+        for y in row * TILEMAP_CHUNK_SIZE..y_max {
+            for x in col * TILEMAP_CHUNK_SIZE..x_max {
+                let tile = layer.tiles[self.get_tile_idx(x, y)];
+                let Some(mut id) = tile.id else {
+                    continue;
+                };
+
+                let key = (layer.tileset_idx, id);
+                if let Some(&idx) = self.tile_animation_ids.get(&key) {
+                    id = self.tile_animations[idx]
+                        .get_tile_id(self.animation_time);
+                    has_animations = true;
+                }
+
+                let positions = self.get_tile_rect(x, y).get_vertices();
+                let texcoords =
+                    tile.get_texcoords(tileset.get_tile_texcoords(id));
+                for i in [0, 1, 2, 0, 2, 3] {
+                    let p = positions[i];
+                    vb.push_vertex(
+                        point![p.x, p.y, 0.0],
+                        None,
+                        Some(WHITE),
+                        Some(texcoords[i]),
+                    );
+                }
+                n_tiles += 1;
+            }
+        }
+
+        let chunk = &mut self.chunks[layer_idx][chunk_idx];
+        chunk.n_tiles = n_tiles;
+        chunk.has_animations = has_animations;
+        chunk.is_dirty = false;
+
+        Some(vb)
+    }
+
+    pub fn get_chunk_vb_idx(
+        &self,
+        layer_idx: usize,
+        col: u32,
+        row: u32,
+    ) -> Option<VertexBufferIdx> {
+        self.get_chunk(layer_idx, col, row).vb_idx
+    }
+
+    pub fn set_chunk_vb_idx(
+        &mut self,
+        layer_idx: usize,
+        col: u32,
+        row: u32,
+        vb_idx: VertexBufferIdx,
+    ) {
+        let (n_cols, _) = self.get_n_chunks();
+        let chunk_idx = (row * n_cols + col) as usize;
+        self.chunks[layer_idx][chunk_idx].vb_idx = Some(vb_idx);
+    }
+
+    // Detaches the chunk vertex buffers (to free them) and marks all
+    // chunks to be rebuilt
+    pub fn take_chunk_vb_idxs(&mut self) -> Vec<VertexBufferIdx> {
+        let mut vb_idxs = vec![];
+        for chunk in self.chunks.iter_mut().flatten() {
+            vb_idxs.extend(chunk.vb_idx.take());
+            chunk.is_dirty = true;
+        }
+
+        vb_idxs
+    }
+
+    pub fn is_chunk_empty(
+        &self,
+        layer_idx: usize,
+        col: u32,
+        row: u32,
+    ) -> bool {
+        self.get_chunk(layer_idx, col, row).n_tiles == 0
+    }
+
+    fn get_chunk(
+        &self,
+        layer_idx: usize,
+        col: u32,
+        row: u32,
+    ) -> TilemapChunk {
+        let (n_cols, _) = self.get_n_chunks();
+
+        self.chunks[layer_idx][(row * n_cols + col) as usize]
+    }
+
+    fn get_tile_idx(&self, x: u32, y: u32) -> usize {
+        if x >= self.width || y >= self.height {
+            panic!(
+                "Tile ({}, {}) is outside of the {}x{} tilemap",
+                x, y, self.width, self.height
+            );
+        }
+
+        (y * self.width + x) as usize
+    }
+
+    fn get_chunk_idx(&self, x: u32, y: u32) -> usize {
+        let (n_cols, _) = self.get_n_chunks();
+        let col = x / TILEMAP_CHUNK_SIZE;
+        let row = y / TILEMAP_CHUNK_SIZE;
+
+        (row * n_cols + col) as usize
+    }
+}
@@ -1,16 +1,22 @@
+use crate::bounding_volume::*;
 use crate::color::*;
+use crate::shapes::Triangle;
 use enum_iterator::Sequence;
-use nalgebra::{Point2, Point3, Vector3};
+use nalgebra::{Point2, Point3, Vector2, Vector3};
 use obj::raw::object::Polygon;
 use obj::raw::parse_obj;
 
 const INIT_VERT_CAP: usize = 1 << 15;
+// Size of the joint matrices array in the shader
+pub const MAX_N_JOINTS: usize = 128;
 
 #[repr(u8)]
 #[derive(Sequence, Copy, Clone, Debug)]
 pub enum VertexFlag {
     HasTexture = 1 << 0,
     HasNormal = 1 << 1,
+    HasSkin = 1 << 2,
+    HasSdf = 1 << 3,
 }
 impl From<VertexFlag> for u32 {
     fn from(e: VertexFlag) -> u32 {
@@ -18,12 +24,38 @@ impl From<VertexFlag> for u32 {
     }
 }
 
+// Rounded box (in the local coordinates of the shape) which the fragment
+// shader uses to compute the anti-aliased coverage. Circles, capsules and
+// rings are all expressed through it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SdfParams {
+    pub half_size: Vector2<f32>,
+    // Bot-left, bot-right, top-right and top-left corner radii
+    pub radii: [f32; 4],
+    // Outline thickness (inwards) or zero for the filled shape
+    pub thickness: f32,
+}
+
+impl SdfParams {
+    pub fn new(
+        half_size: Vector2<f32>,
+        radii: [f32; 4],
+        thickness: f32,
+    ) -> Self {
+        Self { half_size, radii, thickness }
+    }
+}
+
 pub struct VertexBufferCPU {
     positions: Vec<f32>,
     normals: Vec<f32>,
     colors: Vec<f32>,
     texcoords: Vec<f32>,
     flags: Vec<u8>,
+    joints: Vec<u8>,
+    weights: Vec<f32>,
+    sdf_params: Vec<f32>,
+    sdf_radii: Vec<f32>,
     indices: Option<Vec<u32>>,
 }
 
@@ -36,23 +68,50 @@ impl VertexBufferCPU {
         flags: Vec<u8>,
         indices: Option<Vec<u32>>,
     ) -> Self {
+        let n_vertices = positions.len() / 3;
+
         Self {
             positions,
             normals,
             colors,
             texcoords,
             flags,
+            joints: vec![0; n_vertices * 4],
+            weights: vec![0.0; n_vertices * 4],
+            sdf_params: vec![0.0; n_vertices * 4],
+            sdf_radii: vec![0.0; n_vertices * 4],
             indices,
         }
     }
 
     pub fn new_empty() -> Self {
+        Self::with_capacity(INIT_VERT_CAP)
+    }
+
+    pub fn with_capacity(n_vertices: usize) -> Self {
+        let mut vb = Self::new(
+            Vec::with_capacity(n_vertices * 3),
+            Vec::with_capacity(n_vertices * 3),
+            Vec::with_capacity(n_vertices * 4),
+            Vec::with_capacity(n_vertices * 2),
+            Vec::with_capacity(n_vertices),
+            None,
+        );
+        vb.joints.reserve(n_vertices * 4);
+        vb.weights.reserve(n_vertices * 4);
+        vb.sdf_params.reserve(n_vertices * 4);
+        vb.sdf_radii.reserve(n_vertices * 4);
+
+        vb
+    }
+
+    pub fn new_zeros(n_vertices: usize) -> Self {
         Self::new(
-            Vec::with_capacity(INIT_VERT_CAP * 3),
-            Vec::with_capacity(INIT_VERT_CAP * 3),
-            Vec::with_capacity(INIT_VERT_CAP * 4),
-            Vec::with_capacity(INIT_VERT_CAP * 2),
-            Vec::with_capacity(INIT_VERT_CAP * 1),
+            vec![0.0; n_vertices * 3],
+            vec![0.0; n_vertices * 3],
+            vec![0.0; n_vertices * 4],
+            vec![0.0; n_vertices * 2],
+            vec![0; n_vertices],
             None,
         )
     }
@@ -173,6 +232,74 @@ impl VertexBufferCPU {
         }
 
         self.flags.push(flags);
+        self.joints.extend_from_slice(&[0; 4]);
+        self.weights.extend_from_slice(&[0.0; 4]);
+        self.sdf_params.extend_from_slice(&[0.0; 4]);
+        self.sdf_radii.extend_from_slice(&[0.0; 4]);
+    }
+
+    // Local position is relative to the shape center
+    pub fn push_sdf_vertex(
+        &mut self,
+        position: Point3<f32>,
+        color: Option<Color>,
+        local_position: Point2<f32>,
+        params: &SdfParams,
+    ) {
+        self.push_vertex(position, None, color, Some(local_position));
+
+        let n_vertices = self.get_n_vertcies();
+        let flags = &mut self.flags[n_vertices - 1];
+        *flags &= !(VertexFlag::HasTexture as u8);
+        *flags |= VertexFlag::HasSdf as u8;
+
+        let start = (n_vertices - 1) * 4;
+        self.sdf_params[start..start + 4].copy_from_slice(&[
+            params.half_size.x,
+            params.half_size.y,
+            params.thickness,
+            0.0,
+        ]);
+        self.sdf_radii[start..start + 4].copy_from_slice(&params.radii);
+    }
+
+    // Weights of each vertex are normalized to sum up to 1
+    pub fn set_skin(&mut self, joints: Vec<u8>, mut weights: Vec<f32>) {
+        let n_vertices = self.get_n_vertcies();
+        if joints.len() != n_vertices * 4
+            || weights.len() != n_vertices * 4
+        {
+            panic!(
+                "Skin data should have 4 joints and 4 weights per vertex"
+            );
+        }
+
+        if let Some(joint) =
+            joints.iter().find(|&&joint| joint as usize >= MAX_N_JOINTS)
+        {
+            panic!(
+                "Skin joint index {} is out of the max {} joints",
+                joint, MAX_N_JOINTS
+            );
+        }
+
+        for (i, vertex_weights) in weights.chunks_exact_mut(4).enumerate()
+        {
+            let sum: f32 = vertex_weights.iter().sum();
+            if vertex_weights.iter().any(|&w| w < 0.0 || !w.is_finite())
+                || sum <= f32::EPSILON
+            {
+                panic!("Vertex {} has bad skin weights", i);
+            }
+
+            vertex_weights.iter_mut().for_each(|w| *w /= sum);
+        }
+
+        self.joints = joints;
+        self.weights = weights;
+        for flags in self.flags.iter_mut() {
+            *flags |= VertexFlag::HasSkin as u8;
+        }
     }
 
     pub fn get_positions(&self) -> &[f32] {
@@ -195,6 +322,22 @@ impl VertexBufferCPU {
         &self.flags
     }
 
+    pub fn get_joints(&self) -> &[u8] {
+        &self.joints
+    }
+
+    pub fn get_weights(&self) -> &[f32] {
+        &self.weights
+    }
+
+    pub fn get_sdf_params(&self) -> &[f32] {
+        &self.sdf_params
+    }
+
+    pub fn get_sdf_radii(&self) -> &[f32] {
+        &self.sdf_radii
+    }
+
     pub fn get_indices(&self) -> Option<&[u32]> {
         self.indices.as_deref()
     }
@@ -239,6 +382,79 @@ impl VertexBufferCPU {
         &self.flags[from_vertex..(from_vertex + n_vertices)]
     }
 
+    pub fn get_joints_slice(
+        &self,
+        from_vertex: usize,
+        n_vertices: usize,
+    ) -> &[u8] {
+        &self.joints[from_vertex * 4..(from_vertex + n_vertices) * 4]
+    }
+
+    pub fn get_weights_slice(
+        &self,
+        from_vertex: usize,
+        n_vertices: usize,
+    ) -> &[f32] {
+        &self.weights[from_vertex * 4..(from_vertex + n_vertices) * 4]
+    }
+
+    pub fn get_sdf_params_slice(
+        &self,
+        from_vertex: usize,
+        n_vertices: usize,
+    ) -> &[f32] {
+        &self.sdf_params[from_vertex * 4..(from_vertex + n_vertices) * 4]
+    }
+
+    pub fn get_sdf_radii_slice(
+        &self,
+        from_vertex: usize,
+        n_vertices: usize,
+    ) -> &[f32] {
+        &self.sdf_radii[from_vertex * 4..(from_vertex + n_vertices) * 4]
+    }
+
+    pub fn get_triangles(&self) -> Vec<Triangle> {
+        let get_position = |i: usize| {
+            Point3::new(
+                self.positions[i * 3],
+                self.positions[i * 3 + 1],
+                self.positions[i * 3 + 2],
+            )
+        };
+        let get_triangle = |a: usize, b: usize, c: usize| {
+            Triangle::new(
+                get_position(a),
+                get_position(b),
+                get_position(c),
+            )
+        };
+
+        match self.indices.as_ref() {
+            Some(indices) => indices
+                .chunks_exact(3)
+                .map(|t| {
+                    get_triangle(
+                        t[0] as usize,
+                        t[1] as usize,
+                        t[2] as usize,
+                    )
+                })
+                .collect(),
+            None => (0..self.get_n_vertcies() / 3)
+                .map(|i| get_triangle(i * 3, i * 3 + 1, i * 3 + 2))
+                .collect(),
+        }
+    }
+
+    pub fn get_bounding_box(&self) -> BoundingBox {
+        BoundingBox::from_positions(&self.positions)
+    }
+
+    pub fn get_bounding_sphere(&self) -> BoundingSphere {
+        BoundingSphere::from_positions(&self.positions)
+    }
+
     pub fn get_n_vertcies(&self) -> usize {
         self.positions.len() / 3
     }
@@ -295,9 +511,14 @@ impl VertexBufferCPU {
 
     pub fn clear(&mut self) {
         self.positions.clear();
+        self.normals.clear();
         self.colors.clear();
         self.texcoords.clear();
         self.flags.clear();
+        self.joints.clear();
+        self.weights.clear();
+        self.sdf_params.clear();
+        self.sdf_radii.clear();
         self.indices.as_mut().map(|data| data.clear());
     }
 }